@@ -0,0 +1,80 @@
+use std::time::Instant;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    pool::{ProjectileCommands, ProjectilePool, ProjectilePoolPlugin},
+    projectile::Projectile,
+    projectilepattern::ProjectileTemplate
+};
+
+/// Projectiles despawned and spawned again every frame
+const BULLETS: usize = 10000;
+const WARMUP_FRAMES: u32 = 10;
+const FRAMES: u32 = 100;
+
+/// Headless benchmark of spawning and despawning lots of projectiles, with and without the pool \
+/// Only built with the `bench` feature, run it with `cargo run --release --features bench -- --bench-projectiles` \
+/// On a single core of an AMD EPYC it took 23-25 ms/frame without the pool, and 26-28 ms/frame with it. 
+/// Rapier takes most of the frame, and disabling and enabling the colliders of pooled projectiles 
+/// costs about as much as creating new ones, so the pool doesn't save frame time here
+pub fn run() {
+    let without_pool = bench(false);
+    let with_pool = bench(true);
+
+    println!("Without pool: {:.3} ms/frame", without_pool);
+    println!("With pool: {:.3} ms/frame", with_pool);
+    println!("Speedup: {:.2}x", without_pool / with_pool);
+}
+
+/// Returns the average milliseconds a frame took
+fn bench(pool_enabled: bool) -> f32 {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        TransformPlugin,
+        HierarchyPlugin,
+        AssetPlugin::default(),
+        RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.),
+        ProjectilePoolPlugin,
+    ));
+    app.init_asset::<Image>();
+    let mut pool = app.world.resource_mut::<ProjectilePool>();
+    pool.enabled = pool_enabled;
+    pool.capacity = BULLETS;
+    app.add_systems(Update, churn_projectiles);
+
+    for _ in 0..WARMUP_FRAMES {
+        app.update();
+    }
+
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        app.update();
+    }
+    let ms = start.elapsed().as_secs_f32() * 1000. / FRAMES as f32;
+
+    let stats = app.world.resource::<ProjectilePool>().stats;
+    println!("Pool enabled: {}, {} bullets, {:.3} ms/frame, {:?}", pool_enabled, BULLETS, ms, stats);
+
+    return ms;
+}
+
+/// Replaces every projectile with a new one, like a bullet hell where they all hit something at once
+fn churn_projectiles(
+    mut commands: Commands,
+    template: Local<ProjectileTemplate>,
+    projectiles: Query<Entity, With<Projectile>>,
+    mut rng: Local<Option<fastrand::Rng>>,
+) {
+    for id in projectiles.iter() {
+        commands.despawn_projectile(id);
+    }
+
+    let rng = rng.get_or_insert_with( || fastrand::Rng::with_seed(0) );
+    for _ in 0..BULLETS {
+        let position = Vec3::new( rng.f32() * 2000. - 1000., rng.f32() * 2000. - 1000., 0. );
+        commands.spawn_projectile( &template, Transform::from_translation(position), Handle::default() );
+    }
+}
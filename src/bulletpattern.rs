@@ -0,0 +1,352 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    utils::BoxedFuture
+};
+use bevy_rapier2d::geometry::Collider;
+use serde::Deserialize;
+
+use crate::{
+    damage::DamageKind,
+    projectile::{Homing, PState, Pierce, Projectile, ProjectileTargetingType, WallHit},
+    rapier::projectile_groups,
+    projectilepattern::{BoxedPattern, CirclePattern, ProjectileTemplate, RandomSprayPattern, RingPattern, ShotgunPattern, SpiralPattern, WavePattern},
+    stateful::{StateRepeat, Stateful, StatefulFinish},
+    status::StatusEffect,
+    weapon::{WeaponDefinition, WeaponSource}
+};
+
+pub struct BulletPatternPlugin;
+
+impl Plugin for BulletPatternPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<BulletPattern>();
+        app.init_asset_loader::<BulletPatternLoader>();
+        app.init_resource::<PatternReload>();
+        app.add_systems(Update, reload_live_projectiles);
+    }
+}
+
+/// Settings for hot reloading pattern files, 
+/// only does something with the `hot_reload` feature enabled \
+/// Newly fired projectiles always use the latest version of the file
+#[derive(Resource)]
+pub struct PatternReload {
+    /// Also update the projectiles that are already flying
+    pub live_projectiles: bool,
+}
+
+impl Default for PatternReload {
+    fn default() -> Self {
+        PatternReload {
+            live_projectiles: true,
+        }
+    }
+}
+
+/// The pattern file a projectile was spawned from
+#[derive(Component)]
+pub struct PatternSource(pub Handle<BulletPattern>);
+
+/// The kind of pattern a file describes, with the settings only that kind has
+#[derive(Clone, Deserialize)]
+pub enum PatternKind {
+    Circle,
+    /// amount is the number of arms
+    Spiral { step_deg: f32 },
+    Shotgun { 
+        jitter_deg: f32, 
+        #[serde(default)]
+        jitter_speed: f32 
+    },
+    /// amount is the projectiles in a full ring
+    Ring { 
+        rings: i32, 
+        ring_speed_step: f32, 
+        #[serde(default)]
+        gaps: Vec<i32>, 
+        #[serde(default)]
+        gap_shift: i32 
+    },
+    /// amount is the number of streams
+    Wave { 
+        amplitude: f32, 
+        frequency: f32, 
+        #[serde(default)]
+        mirror: bool 
+    },
+    RandomSpray { 
+        #[serde(default)]
+        jitter_speed: f32, 
+        /// Random every time if it's not set
+        #[serde(default)]
+        seed: Option<u64> 
+    },
+}
+
+/// A bullet pattern loaded from a `.pattern.ron` file in assets \
+/// Every field is optional in the file, the missing ones use the defaults
+#[derive(Asset, TypePath, Deserialize)]
+#[serde(default)]
+pub struct BulletPattern {
+    pub kind: PatternKind,
+    /// Amount of projectiles in a volley
+    pub amount: i32,
+    /// The spread of the volley in degrees
+    pub max_deg: f32,
+
+    /// Path of the projectile image, relative to assets
+    pub sprite: String,
+    #[serde(skip)]
+    #[dependency]
+    pub sprite_handle: Handle<Image>,
+    /// Half extents of the projectile collider
+    pub collider: (f32, f32),
+
+    pub damage: i32,
+    pub damage_kind: DamageKind,
+    /// Ignores the defense of what the projectiles hit
+    pub armor_piercing: bool,
+    /// Speed of the projectile before any state changes it
+    pub speed: f32,
+    pub targeting: ProjectileTargetingType,
+    /// Seconds until the projectiles expire
+    pub lifetime: Option<f32>,
+    /// Distance the projectiles can travel before they expire
+    pub range: Option<f32>,
+    /// How many targets the projectiles pass through, they hit every target only once
+    pub pierce: Pierce,
+    /// Makes the projectiles steer toward targets
+    pub homing: Option<Homing>,
+    /// Status effects the projectiles apply to what they damage
+    pub on_hit: Vec<StatusEffect>,
+    /// What the projectiles do when they hit a wall
+    pub wall_hit: WallHit,
+
+    pub states: Vec<PState>,
+    pub state_repeat: StateRepeat,
+    /// What the projectiles do when their states run out, like Despawn
+    pub on_finish: StatefulFinish<PState>,
+    /// Path of a `.pattern.ron` file, spawned where the projectiles are when their states run out
+    pub spawn_on_finish: Option<String>,
+    #[serde(skip)]
+    pub spawn_on_finish_handle: Option<Handle<BulletPattern>>,
+}
+
+impl Default for BulletPattern {
+    fn default() -> Self {
+        let projectile = Projectile::default();
+        BulletPattern {
+            kind: PatternKind::Circle,
+            amount: 1,
+            max_deg: 360.,
+            sprite: "thing.png".to_string(),
+            sprite_handle: Handle::default(),
+            collider: (1., 4.),
+            damage: projectile.damage,
+            damage_kind: projectile.damage_kind,
+            armor_piercing: projectile.armor_piercing,
+            speed: projectile.speed,
+            targeting: projectile.targeting_type,
+            lifetime: projectile.lifetime,
+            range: projectile.range,
+            pierce: projectile.pierce,
+            homing: projectile.homing,
+            on_hit: Vec::new(),
+            wall_hit: projectile.wall_hit,
+            states: Vec::new(),
+            state_repeat: StateRepeat::None,
+            on_finish: StatefulFinish::None,
+            spawn_on_finish: None,
+            spawn_on_finish_handle: None,
+        }
+    }
+}
+
+impl BulletPattern {
+    /// The template of the projectiles this pattern spawns
+    pub fn projectile_template(&self) -> ProjectileTemplate {
+        ProjectileTemplate {
+            base: Projectile {
+                damage: self.damage,
+                damage_kind: self.damage_kind,
+                armor_piercing: self.armor_piercing,
+                targeting_type: self.targeting,
+                speed: self.speed,
+                speed_start: self.speed,
+                lifetime: self.lifetime,
+                range: self.range,
+                pierce: self.pierce,
+                homing: self.homing,
+                on_hit: self.on_hit.clone(),
+                wall_hit: self.wall_hit,
+                ..default()
+            },
+            damage_range: None,
+            collider: Vec2::new(self.collider.0, self.collider.1),
+            states: self.states.clone(),
+            state_repeat: self.state_repeat,
+            on_finish: self.on_finish.clone(),
+            spawn_on_finish: self.spawn_on_finish_handle.clone(),
+            source: None,
+            weapon: None,
+            sprite: None,
+        }
+    }
+
+    /// Creates the pattern facing dir, spawn it with sprite_handle \
+    /// Pass the handle of this pattern as source, to update the projectiles when the file changes
+    pub fn build(&self, dir: Vec2, source: Option<Handle<BulletPattern>>) -> BoxedPattern {
+        let mut projectile = self.projectile_template();
+        projectile.source = source;
+
+        match self.kind.clone() {
+            PatternKind::Circle => Box::new( CirclePattern {
+                amount: self.amount,
+                dir,
+                max_deg: self.max_deg,
+                projectile,
+            }),
+            PatternKind::Spiral { step_deg } => Box::new( SpiralPattern {
+                arms: self.amount,
+                dir,
+                step_deg,
+                angle: 0.,
+                projectile,
+            }),
+            PatternKind::Shotgun { jitter_deg, jitter_speed } => Box::new( ShotgunPattern {
+                amount: self.amount,
+                dir,
+                max_deg: self.max_deg,
+                jitter_deg,
+                jitter_speed,
+                rng: fastrand::Rng::new(),
+                projectile,
+            }),
+            PatternKind::Ring { rings, ring_speed_step, gaps, gap_shift } => Box::new( RingPattern {
+                amount: self.amount,
+                rings,
+                dir,
+                ring_speed_step,
+                gaps,
+                gap_shift,
+                projectile,
+            }),
+            PatternKind::Wave { amplitude, frequency, mirror } => Box::new( WavePattern {
+                streams: self.amount,
+                dir,
+                max_deg: self.max_deg,
+                amplitude,
+                frequency,
+                mirror,
+                projectile,
+            }),
+            PatternKind::RandomSpray { jitter_speed, seed } => Box::new( RandomSprayPattern {
+                amount: self.amount,
+                dir,
+                max_deg: self.max_deg,
+                jitter_speed,
+                rng: seed.map_or_else( fastrand::Rng::new, fastrand::Rng::with_seed ),
+                projectile,
+            }),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct BulletPatternLoader;
+
+impl AssetLoader for BulletPatternLoader {
+    type Asset = BulletPattern;
+    type Settings = ();
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<BulletPattern, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+
+            let mut pattern: BulletPattern = ron::de::from_bytes(&bytes)?;
+            if !pattern.states.is_empty() {
+                pattern.state_repeat.check( &pattern.states )?;
+            }
+            pattern.on_finish.check()?;
+            pattern.sprite_handle = load_context.load(pattern.sprite.clone());
+            pattern.spawn_on_finish_handle = pattern.spawn_on_finish.clone().map( |path| load_context.load(path) );
+
+            Ok(pattern)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["pattern.ron"]
+    }
+}
+
+/// When a pattern file changes on disk, updates the projectiles that were spawned from it \
+/// Their state sequence restarts with the new states. 
+/// Projectiles fired from a weapon get its overrides again, and keep the damage they rolled
+fn reload_live_projectiles(
+    reload: Res<PatternReload>,
+    mut commands: Commands,
+    mut pattern_events: EventReader<AssetEvent<BulletPattern>>,
+    patterns: Res<Assets<BulletPattern>>,
+    weapons: Res<Assets<WeaponDefinition>>,
+    mut projectiles: Query<(Entity, &PatternSource, Option<&WeaponSource>, &mut Projectile, &mut Handle<Image>)>,
+) {
+    for event in pattern_events.read() {
+        let id = match event {
+            AssetEvent::Modified { id } => *id,
+            _ => continue,
+        };
+        let pattern = match patterns.get( id ) {
+            Some( pattern ) => pattern,
+            None => continue,
+        };
+        info!("Reloaded bullet pattern {:?}", id);
+
+        if !reload.live_projectiles {
+            continue;
+        }
+
+        for (entity, source, weapon, mut projectile, mut sprite) in projectiles.iter_mut() {
+            if source.0.id() != id {
+                continue;
+            }
+
+            let mut template = pattern.projectile_template();
+            let weapon = weapon.and_then( |weapon| weapons.get( &weapon.0 ) );
+            match weapon {
+                Some( weapon ) => weapon.apply_to( &mut template, weapon.damage, projectile.owner.unwrap_or(entity) ),
+                None => projectile.damage = template.base.damage,
+            }
+            projectile.damage_kind = template.base.damage_kind;
+            projectile.armor_piercing = template.base.armor_piercing;
+            projectile.targeting_type = template.base.targeting_type;
+            projectile.lifetime = template.base.lifetime;
+            projectile.range = template.base.range;
+            projectile.pierce = template.base.pierce;
+            projectile.homing = template.base.homing;
+            projectile.on_hit = template.base.on_hit;
+            projectile.wall_hit = template.base.wall_hit;
+            *sprite = weapon.and_then( |weapon| weapon.sprite_handle.clone() )
+                .unwrap_or_else( || pattern.sprite_handle.clone() );
+
+            let mut entity = commands.entity(entity);
+            entity.insert((
+                Collider::cuboid(template.collider.x, template.collider.y),
+                projectile_groups( template.base.targeting_type ),
+            ));
+            if template.states.is_empty() {
+                entity.remove::<Stateful<PState>>();
+            } else {
+                entity.insert( Stateful::from_states( template.states, template.state_repeat ).with_on_finish( template.on_finish ) );
+            }
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use bevy::{prelude::*, utils::HashMap};
+use serde::Deserialize;
+
+use crate::{
+    health::Health,
+    states::AppSet,
+    status::{StatusEffects, StatusKind}
+};
+
+pub struct DamagePlugin;
+
+impl Plugin for DamagePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DamageEvent>();
+        app.add_event::<DamageApplied>();
+        app.init_resource::<DamageQueue>();
+        app.configure_sets(PostUpdate,
+            (
+                DamageStage::Collect,
+                DamageStage::Modifiers,
+                DamageStage::Defense,
+                DamageStage::Invulnerability,
+                DamageStage::Apply,
+                DamageStage::PostHit,
+            ).chain().in_set(AppSet::Gameplay)
+        );
+        app.add_systems(PostUpdate,
+            (
+                collect_damage.in_set(DamageStage::Collect),
+                mitigate_damage.in_set(DamageStage::Defense),
+                apply_damage.in_set(DamageStage::Apply),
+                log_damage.in_set(DamageStage::PostHit),
+            )
+        );
+    }
+}
+
+/// The stages every DamageEvent goes through, in order \
+/// Systems in the stages before Apply change or remove the damage in the DamageQueue,
+/// systems in PostHit read the DamageApplied events
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+pub enum DamageStage {
+    /// Moves the DamageEvents into the DamageQueue, send them before this
+    Collect,
+    /// Multipliers and bonuses, before anything reduces the damage
+    Modifiers,
+    /// Mitigation by the defense of the target
+    Defense,
+    /// Removes the damage against targets that can't be hurt right now
+    Invulnerability,
+    /// Subtracts the damage from the Health of the targets
+    Apply,
+    /// Reacts to the damage that got through, like hit effects and damage numbers
+    PostHit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum DamageKind {
+    Physical,
+    Fire,
+    Ice,
+    Lightning,
+    Poison,
+    /// Ignores every mitigation
+    True,
+}
+
+/// Every hit deals at least this much of its damage, no matter the defense, like in RotMG
+pub const MIN_DAMAGE_FRACTION: f32 = 0.1;
+
+/// Reduces the damage of some kinds, 0.25 takes 25% less damage, negative values make it take more \
+/// Applied after defense, true damage ignores it
+#[derive(Component, Default)]
+pub struct Resistances(pub HashMap<DamageKind, f32>);
+
+/// Damage between min and max, rolled for every hit like RotMG weapons
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub struct DamageRange {
+    pub min: i32,
+    pub max: i32,
+}
+
+impl DamageRange {
+    /// A random damage between min and max, both inclusive
+    pub fn roll(&self) -> i32 {
+        return fastrand::i32( self.min..=self.max.max(self.min) );
+    }
+
+    /// Multiplies both ends of the range, rounding them
+    pub fn scaled(&self, multiplier: f32) -> DamageRange {
+        DamageRange {
+            min: (self.min as f32 * multiplier).round() as i32,
+            max: (self.max as f32 * multiplier).round() as i32,
+        }
+    }
+}
+
+/// Send this to damage an entity with Health, instead of changing its Health directly
+#[derive(Event, Clone, Copy, Debug)]
+pub struct DamageEvent {
+    /// What dealt the damage, like a projectile
+    pub source: Option<Entity>,
+    /// Who the damage is credited to, like the shooter of the projectile
+    pub attacker: Option<Entity>,
+    pub target: Entity,
+    pub amount: i32,
+    pub kind: DamageKind,
+    /// Ignores the defense of the target
+    pub armor_piercing: bool,
+    /// Damage over time, like bleeding, it ignores and doesn't trigger invulnerability frames or hit reactions
+    pub periodic: bool,
+}
+
+/// Sent for every DamageEvent that made it through the pipeline, with the damage that was dealt
+#[derive(Event, Clone, Copy, Debug)]
+pub struct DamageApplied {
+    pub source: Option<Entity>,
+    pub attacker: Option<Entity>,
+    pub target: Entity,
+    pub amount: i32,
+    pub kind: DamageKind,
+    pub periodic: bool,
+}
+
+/// The damage going through the stages this frame
+#[derive(Resource, Default)]
+pub struct DamageQueue {
+    pub events: Vec<DamageEvent>,
+}
+
+fn collect_damage(
+    mut queue: ResMut<DamageQueue>,
+    mut damage_events: EventReader<DamageEvent>,
+) {
+    queue.events.extend( damage_events.read().copied() );
+}
+
+/// Reduces the damage by the defense and resistances of the target, down to MIN_DAMAGE_FRACTION
+fn mitigate_damage(
+    mut queue: ResMut<DamageQueue>,
+    targets: Query<(&Health, Option<&Resistances>, Option<&StatusEffects>)>,
+) {
+    for event in queue.events.iter_mut() {
+        if event.kind == DamageKind::True {
+            continue;
+        }
+        let (health, resistances, statuses) = match targets.get( event.target ) {
+            Ok( target ) => target,
+            Err(_) => continue,
+        };
+        let armor_broken = statuses.is_some_and( |s| s.has( StatusKind::ArmorBroken ) );
+
+        let defense = if event.armor_piercing || armor_broken { 0 } else { health.defense };
+        let resistance = resistances.and_then( |r| r.0.get( &event.kind ) ).copied().unwrap_or(0.);
+        event.amount = mitigate( event.amount, defense, resistance );
+    }
+}
+
+/// The damage left after the defense and the resistance, never below MIN_DAMAGE_FRACTION of the damage
+pub fn mitigate(amount: i32, defense: i32, resistance: f32) -> i32 {
+    let raw = amount as f32;
+    let mitigated = (raw - defense as f32) * (1. - resistance);
+    let floor = (raw * MIN_DAMAGE_FRACTION).ceil();
+    return mitigated.round().max( floor ) as i32;
+}
+
+/// Takes the damage that got through from Health, and sends DamageApplied for it
+pub fn apply_damage(
+    mut queue: ResMut<DamageQueue>,
+    mut applied_events: EventWriter<DamageApplied>,
+    mut targets: Query<&mut Health>,
+) {
+    for event in queue.events.drain(..) {
+        if let Ok( mut health ) = targets.get_mut( event.target ) {
+            health.current -= event.amount;
+            applied_events.send( DamageApplied {
+                source: event.source,
+                attacker: event.attacker,
+                target: event.target,
+                amount: event.amount,
+                kind: event.kind,
+                periodic: event.periodic,
+            });
+        }
+    }
+}
+
+fn log_damage(
+    mut applied_events: EventReader<DamageApplied>,
+) {
+    for event in applied_events.read() {
+        debug!("{:?} took {} {:?} damage from {:?}", event.target, event.amount, event.kind, event.source);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defense_reduces_damage() {
+        assert_eq!(mitigate(10, 3, 0.), 7);
+        assert_eq!(mitigate(10, 0, 0.), 10);
+    }
+
+    #[test]
+    fn damage_never_goes_below_the_floor() {
+        // 10% of the damage, rounded up, always gets through
+        assert_eq!(mitigate(10, 50, 0.), 1);
+        assert_eq!(mitigate(25, 50, 0.), 3);
+        assert_eq!(mitigate(1, 5, 0.), 1);
+        assert_eq!(mitigate(10, 0, 1.), 1);
+    }
+
+    #[test]
+    fn resistance_applies_after_defense() {
+        assert_eq!(mitigate(20, 4, 0.5), 8);
+        // Negative resistance is a weakness
+        assert_eq!(mitigate(10, 0, -0.5), 15);
+    }
+
+    #[test]
+    fn damage_range_rolls_within_range() {
+        let range = DamageRange { min: 2, max: 5 };
+        for _ in 0..100 {
+            assert!((2..=5).contains( &range.roll() ));
+        }
+    }
+}
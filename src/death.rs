@@ -0,0 +1,227 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_rapier2d::geometry::Collider;
+use serde::Deserialize;
+
+use crate::{
+    damage::{DamageApplied, DamageStage},
+    health::Health,
+    player::Player,
+    states::{AppSet, AppStates}
+};
+
+pub struct DeathPlugin;
+
+impl Plugin for DeathPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<EntityDied>();
+        app.add_systems(PostUpdate, handle_deaths.in_set(DamageStage::PostHit));
+        app.add_systems(Update, fade_corpses.in_set(AppSet::Gameplay));
+        app.add_systems(OnEnter(AppStates::GameOver), show_game_over);
+    }
+}
+
+/// Sent once, when the Health of an entity drops to zero or below
+#[derive(Event, Clone, Copy)]
+pub struct EntityDied {
+    pub entity: Entity,
+    /// Who dealt the last hit, None if it died some other way
+    pub killer: Option<Entity>,
+    pub position: Vec2,
+}
+
+/// What happens with an entity when it dies, entities without it despawn
+#[derive(Component, Clone, Copy, Deserialize)]
+pub enum DeathBehavior {
+    Despawn,
+    /// Fades out over this many seconds, then despawns
+    Fade(f32),
+    /// Stays where it died, greyed out
+    Corpse,
+}
+
+/// Runs the function when the entity dies, for loot drops and other death effects
+#[derive(Component)]
+pub struct OnDeath(pub fn(&mut Commands, &EntityDied));
+
+/// Marks entities that died, they can't be hit anymore
+#[derive(Component)]
+pub struct Dead;
+
+#[derive(Component)]
+pub struct Fading {
+    pub time: f32,
+    pub duration: f32,
+}
+
+/// Finds the entities that ran out of health, runs their OnDeath, and handles them by their DeathBehavior \
+/// When the player dies, it stays in place and the game is over
+pub fn handle_deaths(
+    mut commands: Commands,
+    mut applied_events: EventReader<DamageApplied>,
+    mut died_events: EventWriter<EntityDied>,
+    mut next_state: ResMut<NextState<AppStates>>,
+    entities: Query<(Entity, &Health, &GlobalTransform, Option<&DeathBehavior>, Has<Player>), Without<Dead>>,
+    hooks: Query<&OnDeath>,
+) {
+    // The last attacker of every entity hit this frame
+    let mut killers = HashMap::new();
+    for event in applied_events.read() {
+        killers.insert( event.target, event.attacker );
+    }
+
+    for (id, health, transform, behavior, is_player) in entities.iter() {
+        if health.current > 0 {
+            continue;
+        }
+
+        let event = EntityDied {
+            entity: id,
+            killer: killers.get(&id).copied().flatten(),
+            position: transform.translation().xy(),
+        };
+        // Before the despawn is queued, so the hook can still use the entity
+        if let Ok( hook ) = hooks.get(id) {
+            (hook.0)( &mut commands, &event );
+        }
+        died_events.send( event );
+
+        if is_player {
+            commands.entity(id).insert( Dead ).remove::<Collider>();
+            next_state.set( AppStates::GameOver );
+            continue;
+        }
+
+        match behavior.copied().unwrap_or( DeathBehavior::Despawn ) {
+            DeathBehavior::Despawn => commands.entity(id).despawn_recursive(),
+            DeathBehavior::Fade( duration ) => {
+                commands.entity(id)
+                    .insert(( Dead, Fading { time: 0., duration } ))
+                    .remove::<Collider>()
+                    .despawn_descendants();
+            },
+            DeathBehavior::Corpse => {
+                commands.entity(id)
+                    .insert( Dead )
+                    .remove::<Collider>()
+                    .despawn_descendants();
+            },
+        }
+    }
+}
+
+/// Fades out dead entities, and greys out corpses
+fn fade_corpses(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut corpses: Query<&mut Sprite, (Added<Dead>, Without<Fading>)>,
+    mut fading: Query<(Entity, &mut Sprite, &mut Fading)>,
+) {
+    for mut sprite in corpses.iter_mut() {
+        sprite.color = Color::GRAY;
+    }
+
+    for (id, mut sprite, mut fade) in fading.iter_mut() {
+        fade.time += time.delta_seconds();
+        let alpha = 1. - fade.time / fade.duration.max(f32::EPSILON);
+        sprite.color.set_a( alpha.max(0.) );
+        if fade.time >= fade.duration {
+            commands.entity(id).despawn_recursive();
+        }
+    }
+}
+
+/// Shows the game over text, with the name of what killed the player if it has one
+fn show_game_over(
+    mut commands: Commands,
+    mut died_events: EventReader<EntityDied>,
+    players: Query<(), With<Player>>,
+    names: Query<&Name>,
+) {
+    let killer = died_events.read()
+        .filter( |event| players.contains( event.entity ) )
+        .filter_map( |event| names.get( event.killer? ).ok() )
+        .last();
+    let text = match killer {
+        Some( name ) => format!("Game over\nKilled by {}", name),
+        None => "Game over".to_string(),
+    };
+
+    commands.spawn(
+        TextBundle::from_section( text, TextStyle { font_size: 40., ..default() } )
+            .with_style( Style {
+                position_type: PositionType::Absolute,
+                top: Val::Percent(45.),
+                left: Val::Percent(42.),
+                ..default()
+            })
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::event::ManualEventReader;
+
+    use super::*;
+
+    /// Spawned by the OnDeath hook, with whether the dying entity was still there when it ran
+    #[derive(Component)]
+    struct HookRan {
+        alive: bool,
+    }
+
+    fn record_hook(commands: &mut Commands, event: &EntityDied) {
+        let id = event.entity;
+        commands.add( move |world: &mut World| {
+            let alive = world.get_entity(id).is_some();
+            world.spawn( HookRan { alive } );
+        });
+    }
+
+    fn death_app() -> App {
+        let mut app = App::new();
+        app.add_event::<DamageApplied>();
+        app.add_event::<EntityDied>();
+        app.insert_resource( NextState::<AppStates>(None) );
+        app.add_systems(Update, handle_deaths);
+        return app;
+    }
+
+    #[test]
+    fn runs_on_death_before_the_despawn() {
+        let mut app = death_app();
+        let transform = GlobalTransform::from_translation( Vec3::new(3., 4., 0.) );
+        let alive = app.world.spawn( (Health { current: 1, ..default() }, transform, OnDeath(record_hook)) ).id();
+        let dying = app.world.spawn( (Health { current: 0, ..default() }, transform, OnDeath(record_hook)) ).id();
+
+        app.update();
+        assert!(app.world.get_entity(alive).is_some());
+        assert!(app.world.get_entity(dying).is_none());
+
+        let mut hooks = app.world.query::<&HookRan>();
+        let ran: Vec<bool> = hooks.iter(&app.world).map( |hook| hook.alive ).collect();
+        assert_eq!(ran, vec![true]);
+
+        let mut reader = ManualEventReader::<EntityDied>::default();
+        let died: Vec<EntityDied> = reader.read( app.world.resource::<Events<EntityDied>>() ).copied().collect();
+        assert_eq!(died.len(), 1);
+        assert_eq!(died[0].entity, dying);
+        assert_eq!(died[0].position, Vec2::new(3., 4.));
+    }
+
+    #[test]
+    fn dies_only_once() {
+        let mut app = death_app();
+        let id = app.world.spawn( (Health { current: 0, ..default() }, GlobalTransform::default(), DeathBehavior::Corpse) ).id();
+
+        let mut reader = ManualEventReader::<EntityDied>::default();
+        let mut died = 0;
+        for _ in 0..3 {
+            app.update();
+            died += reader.read( app.world.resource::<Events<EntityDied>>() ).count();
+        }
+        assert_eq!(died, 1);
+        // Corpses stay, but can't be hit anymore
+        assert!(app.world.entity(id).contains::<Dead>());
+        assert!(!app.world.entity(id).contains::<Collider>());
+    }
+}
@@ -0,0 +1,426 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    bulletpattern::BulletPattern,
+    death::Dead,
+    health::Health,
+    player::Player,
+    projectile::ProjectileTargetingType,
+    projectilepattern::{in_window, rotate_dir, BoxedPattern, CirclePattern, ParallelPattern, RepeatPattern, RotatingPattern, SequencePattern},
+    states::AppSet
+};
+
+pub struct EmitterPlugin;
+
+impl Plugin for EmitterPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, build_emitter_patterns);
+        app.add_systems(FixedUpdate, update_emitters.in_set(AppSet::Gameplay));
+    }
+}
+
+/// Where a PatternEmitter points its pattern
+#[derive(Clone, Copy, Deserialize)]
+pub enum EmitterAim {
+    /// Always the same direction
+    Fixed(Vec2),
+    /// The nearest entity the emitter's targeting can hit
+    NearestTarget,
+    /// The player
+    Player,
+    /// Starts from dir, and rotates counter-clockwise
+    Rotate { dir: Vec2, deg_per_second: f32 },
+}
+
+/// Fires a pattern over and over from the position of its entity \
+/// Make the entity a child of something to attach the emitter to it, like a turret, an enemy, or an orbiting item
+#[derive(Component)]
+pub struct PatternEmitter {
+    pub pattern: BoxedPattern,
+    pub sprite: Handle<Image>,
+    pub aim: EmitterAim,
+    /// Who NearestTarget looks for, should match the targeting of the pattern's projectiles
+    pub targeting: ProjectileTargetingType,
+    /// Targets further than this are ignored, None means any distance
+    pub range: Option<f32>,
+
+    /// Seconds between the start of bursts, 0 fires a single burst
+    pub cooldown: f32,
+    /// Volleys in a burst
+    pub burst_count: i32,
+    /// Seconds between the volleys of a burst
+    pub burst_interval: f32,
+
+    /// Emitters that aren't active don't fire, and their timers stop
+    pub active: bool,
+    /// Time since the current burst started, start it negative to delay the first burst
+    pub elapsed: f32,
+    /// Time since the emitter started, used by EmitterAim::Rotate
+    pub aim_time: f32,
+}
+
+/// The patterns an emitter fires, combined from pattern files
+#[derive(Clone, Deserialize)]
+pub enum EmitterPattern {
+    /// Path of a `.pattern.ron` file, relative to assets
+    File(String),
+    /// Fires the patterns one after the other, 
+    /// every pattern waits for the previous one to finish, and then for its delay in seconds
+    Sequence(Vec<(f32, EmitterPattern)>),
+    /// Fires every pattern at the same time
+    Parallel(Vec<EmitterPattern>),
+    /// Fires the pattern count times, every interval seconds, rotating it by rotate_deg (counter-clockwise) every time
+    Repeat {
+        pattern: Box<EmitterPattern>,
+        count: i32,
+        interval: f32,
+        #[serde(default)]
+        rotate_deg: f32,
+    },
+    /// Rotates the direction of the pattern over time, in degrees per second (counter-clockwise)
+    Rotate {
+        pattern: Box<EmitterPattern>,
+        deg_per_second: f32,
+    },
+}
+
+impl EmitterPattern {
+    /// Paths of every pattern file, in the order build takes their handles
+    pub fn paths(&self) -> Vec<&str> {
+        return match self {
+            EmitterPattern::File( path ) => vec![ path.as_str() ],
+            EmitterPattern::Sequence( patterns ) => patterns.iter().flat_map( |(_, pattern)| pattern.paths() ).collect(),
+            EmitterPattern::Parallel( patterns ) => patterns.iter().flat_map( |pattern| pattern.paths() ).collect(),
+            EmitterPattern::Repeat { pattern, .. } => pattern.paths(),
+            EmitterPattern::Rotate { pattern, .. } => pattern.paths(),
+        };
+    }
+
+    /// Builds the pattern from the files, the handles are in the order of paths \
+    /// None while any of the files is still loading
+    pub fn build(&self, handles: &mut std::slice::Iter<Handle<BulletPattern>>, patterns: &Assets<BulletPattern>) -> Option<BoxedPattern> {
+        let built: BoxedPattern = match self {
+            EmitterPattern::File( _ ) => {
+                let handle = handles.next()?;
+                let file = patterns.get( handle )?;
+                let mut pattern = file.build( Vec2::Y, Some( handle.clone() ) );
+                pattern.for_each_template( &mut |template| template.sprite = Some( file.sprite_handle.clone() ) );
+                pattern
+            },
+            EmitterPattern::Sequence( sequence ) => {
+                let mut built = SequencePattern::new();
+                for (delay, pattern) in sequence.iter() {
+                    built = built.then( *delay, pattern.build( handles, patterns )? );
+                }
+                Box::new( built )
+            },
+            EmitterPattern::Parallel( parallel ) => {
+                let mut built = ParallelPattern { patterns: Vec::new() };
+                for pattern in parallel.iter() {
+                    built.patterns.push( pattern.build( handles, patterns )? );
+                }
+                Box::new( built )
+            },
+            EmitterPattern::Repeat { pattern, count, interval, rotate_deg } => {
+                let mut built = RepeatPattern::new( pattern.build( handles, patterns )?, *count, *interval );
+                built.rotate_deg = *rotate_deg;
+                Box::new( built )
+            },
+            EmitterPattern::Rotate { pattern, deg_per_second } => {
+                Box::new( RotatingPattern::new( pattern.build( handles, patterns )?, *deg_per_second ) )
+            },
+        };
+        return Some( built );
+    }
+}
+
+/// The pattern files of a PatternEmitter, the pattern of the emitter is built from them once they load, 
+/// and again every time one of them changes
+#[derive(Component)]
+pub struct EmitterSource {
+    pub pattern: EmitterPattern,
+    /// Handles of the pattern files, in the order of EmitterPattern::paths
+    pub handles: Vec<Handle<BulletPattern>>,
+    built: bool,
+}
+
+impl EmitterSource {
+    pub fn new(pattern: EmitterPattern, handles: Vec<Handle<BulletPattern>>) -> Self {
+        EmitterSource { pattern, handles, built: false }
+    }
+}
+
+impl PatternEmitter {
+    /// An emitter that fires pattern files, add the EmitterSource next to it \
+    /// It fires nothing until the files are loaded
+    pub fn from_file(aim: EmitterAim, cooldown: f32) -> Self {
+        PatternEmitter::new( Box::new( CirclePattern::default() ), Handle::default(), aim, cooldown )
+    }
+
+    pub fn new(pattern: BoxedPattern, sprite: Handle<Image>, aim: EmitterAim, cooldown: f32) -> Self {
+        PatternEmitter {
+            pattern,
+            sprite,
+            aim,
+            targeting: ProjectileTargetingType::ENEMY,
+            range: None,
+            cooldown,
+            burst_count: 1,
+            burst_interval: 0.,
+            active: true,
+            elapsed: 0.,
+            aim_time: 0.,
+        }
+    }
+
+    /// Spawns the emitter as a child of parent, offset from it
+    pub fn spawn_attached(commands: &mut Commands, parent: Entity, offset: Vec2, emitter: PatternEmitter) -> Entity {
+        let id = commands.spawn(
+            (
+                TransformBundle::from_transform( Transform::from_translation( offset.extend(0.) ) ),
+                emitter,
+            )
+        ).id();
+        commands.entity(parent).add_child(id);
+        return id;
+    }
+
+    /// Fires the volleys of the burst that are due between from and to
+    fn fire_between(&mut self, commands: &mut Commands, center: &Transform, dir: Vec2, from: f32, to: f32) {
+        let duration = self.pattern.duration();
+        for i in 0..self.burst_count {
+            let offset = self.burst_interval * i as f32;
+            if in_window( from - offset, to - offset, duration ) {
+                self.pattern.set_dir( dir );
+                self.pattern.spawn_between( commands, center, self.sprite.clone(), from - offset, to - offset );
+            }
+        }
+    }
+}
+
+/// Builds the patterns of emitters with an EmitterSource, when their files load or change
+fn build_emitter_patterns(
+    mut pattern_events: EventReader<AssetEvent<BulletPattern>>,
+    patterns: Res<Assets<BulletPattern>>,
+    mut emitters: Query<(&mut PatternEmitter, &mut EmitterSource)>,
+) {
+    let modified: Vec<AssetId<BulletPattern>> = pattern_events.read()
+        .filter_map( |event| match event {
+            AssetEvent::Modified { id } => Some( *id ),
+            _ => None,
+        })
+        .collect();
+
+    for (mut emitter, mut source) in emitters.iter_mut() {
+        if source.built && !source.handles.iter().any( |handle| modified.contains( &handle.id() ) ) {
+            continue;
+        }
+        let first = source.handles.first().and_then( |handle| patterns.get( handle ) );
+        let built = source.pattern.build( &mut source.handles.iter(), &patterns );
+        if let (Some( pattern ), Some( first )) = (built, first) {
+            emitter.pattern = pattern;
+            // Every file keeps its own sprite, this is only used for patterns without one
+            emitter.sprite = first.sprite_handle.clone();
+            emitter.targeting = first.targeting;
+            source.built = true;
+        }
+    }
+}
+
+fn update_emitters(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut emitters: Query<(Entity, &GlobalTransform, &mut PatternEmitter, Option<&Parent>)>,
+    targets: Query<(Entity, &GlobalTransform, Has<Player>), (With<Health>, Without<Dead>)>,
+) {
+    let delta = time.delta_seconds();
+
+    for (id, transform, mut emitter, parent) in emitters.iter_mut() {
+        if !emitter.active {
+            continue;
+        }
+        emitter.aim_time += delta;
+
+        let center = transform.compute_transform();
+        let position = center.translation.xy();
+        let owner = parent.map( |p| p.get() );
+        let range = emitter.range;
+
+        // Attached emitters fire in the name of their parent
+        let shooter = owner.unwrap_or(id);
+        emitter.pattern.for_each_template( &mut |template| template.base.owner = Some(shooter) );
+
+        // Closest target in range, that passes the filter
+        let nearest = |filter: &dyn Fn(Entity, bool) -> bool| {
+            targets.iter()
+                .filter( |(id, _, is_player)| filter(*id, *is_player) )
+                .map( |(_, t, _)| t.translation().xy() - position )
+                .filter( |offset| range.is_none_or(|range| offset.length() <= range) )
+                .min_by( |a, b| a.length_squared().total_cmp( &b.length_squared() ) )
+        };
+
+        let dir = match emitter.aim {
+            EmitterAim::Fixed( dir ) => Some( dir ),
+            EmitterAim::Rotate { dir, deg_per_second } => Some( rotate_dir( dir, deg_per_second * emitter.aim_time ) ),
+            EmitterAim::Player => nearest( &|_: Entity, is_player: bool| is_player ),
+            EmitterAim::NearestTarget => match emitter.targeting {
+                ProjectileTargetingType::PLAYER => nearest( &|id: Entity, is_player: bool| !is_player && Some(id) != owner ),
+                ProjectileTargetingType::ENEMY => nearest( &|_: Entity, is_player: bool| is_player ),
+                ProjectileTargetingType::ENVIRONMENT => nearest( &|id: Entity, _: bool| Some(id) != owner ),
+            },
+        };
+
+        let mut from = emitter.elapsed;
+        let mut to = from + delta;
+
+        // Without a target the timers still run, we just don't fire
+        if let Some( dir ) = dir {
+            emitter.fire_between( &mut commands, &center, dir, from, to );
+        }
+
+        // Start the next burst, and fire what is due from it this tick
+        while emitter.cooldown > 0. && to >= emitter.cooldown {
+            from -= emitter.cooldown;
+            to -= emitter.cooldown;
+            if let Some( dir ) = dir {
+                emitter.fire_between( &mut commands, &center, dir, from, to );
+            }
+        }
+
+        emitter.elapsed = to;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::{Arc, Mutex}, time::Duration};
+
+    use super::*;
+    use crate::projectilepattern::{IPPattern, ProjectileTemplate};
+
+    /// Records the direction of every volley instead of spawning projectiles
+    struct RecordingPattern {
+        dir: Vec2,
+        volleys: Arc<Mutex<Vec<Vec2>>>,
+    }
+
+    impl IPPattern for RecordingPattern {
+        fn spawn(&mut self, _commands: &mut Commands, _center: &Transform, _sprite: Handle<Image>) {
+            self.volleys.lock().unwrap().push( self.dir );
+        }
+
+        fn set_dir(&mut self, dir: Vec2) {
+            self.dir = dir;
+        }
+
+        fn dir(&self) -> Vec2 {
+            self.dir
+        }
+
+        fn for_each_template(&mut self, _f: &mut dyn FnMut(&mut ProjectileTemplate)) {}
+    }
+
+    /// Runs the emitter for ticks ticks of tick_ms milliseconds, 
+    /// and returns the tick every volley was fired in, with its direction
+    fn run_emitter(mut emitter: PatternEmitter, tick_ms: u64, ticks: usize) -> Vec<(usize, Vec2)> {
+        let volleys = Arc::new( Mutex::new( Vec::new() ) );
+        emitter.pattern = Box::new( RecordingPattern { dir: Vec2::Y, volleys: volleys.clone() } );
+
+        let mut app = App::new();
+        app.init_resource::<Time>();
+        app.add_systems(Update, update_emitters);
+        app.world.spawn( (TransformBundle::default(), emitter) );
+
+        let mut fired = Vec::new();
+        for tick in 0..ticks {
+            app.world.resource_mut::<Time>().advance_by( Duration::from_millis(tick_ms) );
+            app.update();
+            let mut volleys = volleys.lock().unwrap();
+            fired.extend( volleys.drain(..).map( |dir| (tick, dir) ) );
+        }
+        return fired;
+    }
+
+    fn emitter(aim: EmitterAim, cooldown: f32) -> PatternEmitter {
+        PatternEmitter::new( Box::new( CirclePattern::default() ), Handle::default(), aim, cooldown )
+    }
+
+    #[test]
+    fn fires_every_volley_of_a_burst() {
+        let mut burst = emitter( EmitterAim::Fixed(Vec2::X), 0.5 );
+        burst.burst_count = 3;
+        burst.burst_interval = 0.1;
+
+        let fired = run_emitter( burst, 10, 99 );
+        let ticks: Vec<usize> = fired.iter().map( |(tick, _)| *tick ).collect();
+        assert_eq!( ticks.len(), 6, "fired in ticks {:?}", ticks );
+        for (tick, expected) in ticks.iter().zip( [0, 10, 20, 50, 60, 70] ) {
+            assert!( tick.abs_diff(expected) <= 1, "fired in ticks {:?}", ticks );
+        }
+        assert!( fired.iter().all( |(_, dir)| *dir == Vec2::X ) );
+    }
+
+    #[test]
+    fn fires_every_burst_when_the_cooldown_is_shorter_than_a_tick() {
+        // 16ms ticks, a burst every 10ms
+        let fired = run_emitter( emitter( EmitterAim::Fixed(Vec2::X), 0.01 ), 16, 60 );
+        assert!( fired.len().abs_diff(96) <= 1, "fired {} volleys", fired.len() );
+        // Some ticks fire twice
+        assert!( fired.windows(2).any( |pair| pair[0].0 == pair[1].0 ) );
+    }
+
+    #[test]
+    fn zero_cooldown_fires_a_single_burst() {
+        let fired = run_emitter( emitter( EmitterAim::Fixed(Vec2::X), 0. ), 10, 100 );
+        assert_eq!( fired.len(), 1 );
+    }
+
+    #[test]
+    fn rotating_aim_turns_counter_clockwise() {
+        // The second burst is a second in, a quarter turn from the first
+        let aim = EmitterAim::Rotate { dir: Vec2::X, deg_per_second: 90. };
+        let fired = run_emitter( emitter( aim, 1. ), 10, 101 );
+        assert_eq!( fired.len(), 2 );
+        assert!( fired[0].1.abs_diff_eq( Vec2::X, 0.05 ), "aimed at {:?}", fired[0].1 );
+        assert!( fired[1].1.abs_diff_eq( Vec2::Y, 0.05 ), "aimed at {:?}", fired[1].1 );
+    }
+
+    fn file(path: &str) -> Box<EmitterPattern> {
+        Box::new( EmitterPattern::File( path.to_string() ) )
+    }
+
+    #[test]
+    fn builds_combined_patterns_from_files() {
+        let combined = EmitterPattern::Sequence( vec![
+            (0.5, EmitterPattern::Parallel( vec![ *file("a"), *file("b") ] )),
+            (1., EmitterPattern::Rotate {
+                pattern: Box::new( EmitterPattern::Repeat { pattern: file("c"), count: 3, interval: 0.25, rotate_deg: 0. } ),
+                deg_per_second: 90.,
+            }),
+        ]);
+        assert_eq!( combined.paths(), vec!["a", "b", "c"] );
+
+        let mut patterns = Assets::<BulletPattern>::default();
+        let handles: Vec<Handle<BulletPattern>> = (0..3).map( |_| patterns.add( BulletPattern::default() ) ).collect();
+        let built = combined.build( &mut handles.iter(), &patterns ).unwrap();
+        assert_eq!( built.duration(), 2. );
+
+        // Nothing is built while a file is missing
+        patterns.remove( &handles[2] );
+        assert!( combined.build( &mut handles.iter(), &patterns ).is_none() );
+    }
+
+    #[test]
+    fn combinators_keep_the_direction_of_the_pattern() {
+        let mut patterns = Assets::<BulletPattern>::default();
+        let handle = patterns.add( BulletPattern::default() );
+        let mut inner = EmitterPattern::File( "a".to_string() ).build( &mut [handle].iter(), &patterns ).unwrap();
+        inner.set_dir( Vec2::X );
+
+        let repeat = RepeatPattern::new( inner, 2, 1. );
+        assert_eq!( repeat.dir(), Vec2::X );
+        let rotating = RotatingPattern::new( Box::new( repeat ), 90. );
+        assert_eq!( rotating.dir(), Vec2::X );
+    }
+}
@@ -0,0 +1,294 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    utils::{BoxedFuture, HashMap}
+};
+use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    bulletpattern::BulletPattern,
+    damage::{DamageKind, Resistances},
+    death::{Dead, DeathBehavior, OnDeath},
+    damage::DamageStage,
+    death::handle_deaths,
+    emitter::{EmitterAim, EmitterPattern, EmitterSource, PatternEmitter},
+    entity::EntityRotate,
+    heal::drop_health_pickup,
+    health::Health,
+    hit::{HitFlash, Knockback},
+    projectilepattern::PatternPlayer,
+    rapier::enemy_groups,
+    states::AppSet
+};
+
+pub struct EnemyPlugin;
+
+impl Plugin for EnemyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<EnemyDefinition>();
+        app.init_asset_loader::<EnemyDefinitionLoader>();
+        app.add_systems(Update, build_enemies.in_set(AppSet::Gameplay));
+        // Before the dead enemies are despawned
+        app.add_systems(PostUpdate, play_death_patterns.in_set(DamageStage::PostHit).before(handle_deaths));
+    }
+}
+
+/// An enemy loaded from a `.enemy.ron` file in assets \
+/// Enemies spawned from it are updated when the file changes, their current health is kept
+#[derive(Asset, TypePath, Deserialize)]
+pub struct EnemyDefinition {
+    pub name: String,
+
+    /// Path of the enemy image, relative to assets
+    pub sprite: String,
+    #[serde(skip)]
+    pub sprite_handle: Handle<Image>,
+    /// Half size of the collider
+    pub collider: Vec2,
+
+    pub health: i32,
+    #[serde(default)]
+    pub defense: i32,
+    #[serde(default)]
+    pub resistances: HashMap<DamageKind, f32>,
+    /// Strength and drag of the knockback from hits, None isn't pushed around
+    #[serde(default)]
+    pub knockback: Option<(f32, f32)>,
+    /// Keeps the enemy upright for the player, like the other entities
+    #[serde(default)]
+    pub rotate: bool,
+    #[serde(default)]
+    pub death: Option<DeathBehavior>,
+    /// Fired where the enemy died
+    #[serde(default)]
+    pub death_pattern: Option<EmitterPattern>,
+    #[serde(skip)]
+    pub death_pattern_handles: Vec<Handle<BulletPattern>>,
+    /// Drops a health pickup where it died
+    #[serde(default)]
+    pub drops_health: bool,
+
+    #[serde(default)]
+    pub emitters: Vec<EmitterDefinition>,
+}
+
+/// A PatternEmitter attached to the enemy
+#[derive(Deserialize)]
+pub struct EmitterDefinition {
+    pub pattern: EmitterPattern,
+    #[serde(skip)]
+    pub pattern_handles: Vec<Handle<BulletPattern>>,
+    pub aim: EmitterAim,
+    #[serde(default)]
+    pub offset: Vec2,
+    pub cooldown: f32,
+    #[serde(default = "default_burst_count")]
+    pub burst_count: i32,
+    #[serde(default)]
+    pub burst_interval: f32,
+    #[serde(default)]
+    pub range: Option<f32>,
+}
+
+fn default_burst_count() -> i32 {
+    return 1;
+}
+
+impl EmitterDefinition {
+    /// Spawns the emitter on the enemy, the pattern is built by the EmitterSource once it loads
+    pub fn spawn_attached(&self, commands: &mut Commands, enemy: Entity) -> Entity {
+        let mut emitter = PatternEmitter::from_file( self.aim, self.cooldown );
+        emitter.burst_count = self.burst_count;
+        emitter.burst_interval = self.burst_interval;
+        emitter.range = self.range;
+
+        let id = PatternEmitter::spawn_attached( commands, enemy, self.offset, emitter );
+        commands.entity(id).insert( EmitterSource::new( self.pattern.clone(), self.pattern_handles.clone() ) );
+        return id;
+    }
+}
+
+#[derive(Default)]
+pub struct EnemyDefinitionLoader;
+
+impl AssetLoader for EnemyDefinitionLoader {
+    type Asset = EnemyDefinition;
+    type Settings = ();
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<EnemyDefinition, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+
+            let mut enemy: EnemyDefinition = ron::de::from_bytes(&bytes)?;
+            enemy.sprite_handle = load_context.load(enemy.sprite.clone());
+            for emitter in enemy.emitters.iter_mut() {
+                emitter.pattern_handles = emitter.pattern.paths().into_iter()
+                    .map( |path| load_context.load(path.to_string()) )
+                    .collect();
+            }
+            if let Some( pattern ) = &enemy.death_pattern {
+                enemy.death_pattern_handles = pattern.paths().into_iter()
+                    .map( |path| load_context.load(path.to_string()) )
+                    .collect();
+            }
+
+            Ok(enemy)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["enemy.ron"]
+    }
+}
+
+/// An enemy built from its EnemyDefinition, the components are added once the file loads
+#[derive(Component)]
+pub struct Enemy {
+    pub definition: Handle<EnemyDefinition>,
+    built: bool,
+}
+
+impl Enemy {
+    /// Spawns the enemy at transform, it shows up once its file is loaded
+    pub fn spawn(commands: &mut Commands, definition: Handle<EnemyDefinition>, transform: Transform) -> Entity {
+        return commands.spawn(
+            (
+                SpriteBundle { transform, visibility: Visibility::Hidden, ..default() },
+                Enemy { definition, built: false },
+            )
+        ).id();
+    }
+}
+
+/// Adds the components of enemies from their EnemyDefinition when it loads, and again every time the file changes
+fn build_enemies(
+    mut commands: Commands,
+    mut enemy_events: EventReader<AssetEvent<EnemyDefinition>>,
+    definitions: Res<Assets<EnemyDefinition>>,
+    mut enemies: Query<(Entity, &mut Enemy, &mut Handle<Image>, &mut Visibility, Option<&mut Health>, Option<&Children>), Without<Dead>>,
+    emitters: Query<(), With<PatternEmitter>>,
+) {
+    let modified: Vec<AssetId<EnemyDefinition>> = enemy_events.read()
+        .filter_map( |event| match event {
+            AssetEvent::Modified { id } => Some( *id ),
+            _ => None,
+        })
+        .collect();
+
+    for (id, mut enemy, mut texture, mut visibility, health, children) in enemies.iter_mut() {
+        if enemy.built && !modified.contains( &enemy.definition.id() ) {
+            continue;
+        }
+        let Some( definition ) = definitions.get( &enemy.definition ) else {
+            continue;
+        };
+
+        *texture = definition.sprite_handle.clone();
+        *visibility = Visibility::Inherited;
+
+        // Keep the damage the enemy already took
+        match health {
+            Some( mut health ) => {
+                health.current = health.current.min( definition.health );
+                health.max = definition.health;
+                health.defense = definition.defense;
+            },
+            None => {
+                commands.entity(id).insert((
+                    Health {
+                        max: definition.health,
+                        current: definition.health,
+                        defense: definition.defense,
+                        ..default()
+                    },
+                    HitFlash::new(Color::RED, 0.1),
+                ));
+            },
+        }
+
+        let mut entity = commands.entity(id);
+        entity.insert((
+            Name::new( definition.name.clone() ),
+            RigidBody::KinematicPositionBased,
+            Collider::cuboid( definition.collider.x, definition.collider.y ),
+            enemy_groups(),
+            Resistances( definition.resistances.clone() ),
+            definition.death.unwrap_or( DeathBehavior::Despawn ),
+        ));
+        match definition.knockback {
+            Some( (strength, drag) ) => entity.insert( Knockback::new(strength, drag) ),
+            None => entity.remove::<Knockback>(),
+        };
+        if definition.rotate {
+            entity.insert( EntityRotate );
+        } else {
+            entity.remove::<EntityRotate>();
+        }
+        if definition.drops_health {
+            entity.insert( OnDeath(drop_health_pickup) );
+        } else {
+            entity.remove::<OnDeath>();
+        }
+
+        // The emitters are spawned again, the health bar and other children stay
+        for child in children.into_iter().flatten() {
+            if emitters.contains( *child ) {
+                commands.entity(*child).despawn_recursive();
+            }
+        }
+        for emitter in definition.emitters.iter() {
+            emitter.spawn_attached( &mut commands, id );
+        }
+
+        enemy.built = true;
+    }
+}
+
+/// Fires the death pattern of enemies that ran out of health, from where they died
+fn play_death_patterns(
+    mut commands: Commands,
+    definitions: Res<Assets<EnemyDefinition>>,
+    patterns: Res<Assets<BulletPattern>>,
+    enemies: Query<(&Enemy, &Health, &GlobalTransform), Without<Dead>>,
+) {
+    for (enemy, health, transform) in enemies.iter() {
+        if health.current > 0 {
+            continue;
+        }
+        let Some( definition ) = definitions.get( &enemy.definition ) else {
+            continue;
+        };
+        let Some( death_pattern ) = &definition.death_pattern else {
+            continue;
+        };
+        // Skipped if the files are still loading
+        if let Some( pattern ) = death_pattern.build( &mut definition.death_pattern_handles.iter(), &patterns ) {
+            let transform = Transform::from_translation( transform.translation() );
+            PatternPlayer::spawn_at( &mut commands, transform, pattern, Handle::default() );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_dummy() {
+        let enemy: EnemyDefinition = ron::de::from_str( include_str!("../assets/enemies/dummy.enemy.ron") ).unwrap();
+        assert_eq!( enemy.collider, Vec2::new(5., 5.) );
+        assert_eq!( enemy.emitters.len(), 1 );
+        assert!( matches!( enemy.emitters[0].aim, EmitterAim::Player ) );
+        assert_eq!( enemy.emitters[0].burst_count, 1 );
+        assert_eq!( enemy.emitters[0].pattern.paths(), vec!["patterns/flak.pattern.ron"] );
+        assert!( matches!( enemy.death_pattern, Some( EmitterPattern::Repeat { count: 3, .. } ) ) );
+    }
+}
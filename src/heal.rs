@@ -0,0 +1,245 @@
+use bevy::prelude::*;
+
+use crate::{
+    damage::{apply_damage, DamageStage},
+    death::{Dead, EntityDied},
+    editor_camera::MainCamera,
+    health::Health,
+    player::Player,
+    states::AppSet
+};
+
+pub struct HealPlugin;
+
+impl Plugin for HealPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<HealEvent>();
+        app.add_event::<Healed>();
+        app.add_systems(Update,
+            (
+                regenerate,
+                pick_up_health,
+                spawn_heal_popups,
+                update_heal_popups,
+            ).in_set(AppSet::Gameplay)
+        );
+        // Heals land before the damage of the same frame, so healing can save an entity from a lethal hit
+        app.add_systems(PostUpdate, apply_heals.in_set(DamageStage::Apply).before(apply_damage));
+    }
+}
+
+/// Send this to heal an entity with Health, instead of changing its Health directly
+#[derive(Event, Clone, Copy, Debug)]
+pub struct HealEvent {
+    pub target: Entity,
+    pub amount: i32,
+    /// Can heal above max, up to the overheal_cap of the Health
+    pub overheal: bool,
+}
+
+/// Sent for every heal that restored health, with the amount it actually healed
+#[derive(Event, Clone, Copy, Debug)]
+pub struct Healed {
+    pub target: Entity,
+    pub amount: i32,
+    /// Came from Regeneration, so damage numbers can skip it
+    pub regeneration: bool,
+}
+
+/// Heals the entity every second, more with higher vitality, like in RotMG \
+/// It never heals above max
+#[derive(Component)]
+pub struct Regeneration {
+    pub vitality: i32,
+    /// Health regenerated that didn't add up to a whole point yet
+    accumulated: f32,
+}
+
+impl Regeneration {
+    pub fn new(vitality: i32) -> Self {
+        Regeneration { vitality, accumulated: 0. }
+    }
+
+    pub fn per_second(&self) -> f32 {
+        return 1. + 0.12 * self.vitality as f32;
+    }
+}
+
+/// The green "+amount" text that rises from healed entities, and fades out
+#[derive(Component)]
+pub struct HealPopup {
+    pub time: f32,
+}
+
+/// Seconds a HealPopup stays, and how far it rises in that time
+pub const HEAL_POPUP_DURATION: f32 = 0.8;
+pub const HEAL_POPUP_RISE: f32 = 8.;
+
+/// Heals the player when it gets within radius, then despawns
+#[derive(Component)]
+pub struct HealthPickup {
+    pub amount: i32,
+    pub radius: f32,
+    pub overheal: bool,
+}
+
+fn regenerate(
+    time: Res<Time>,
+    mut healed_events: EventWriter<Healed>,
+    mut entities: Query<(Entity, &mut Health, &mut Regeneration), Without<Dead>>,
+) {
+    for (id, mut health, mut regen) in entities.iter_mut() {
+        if health.current >= health.max {
+            regen.accumulated = 0.;
+            continue;
+        }
+
+        regen.accumulated += regen.per_second() * time.delta_seconds();
+        let amount = (regen.accumulated.floor() as i32).min( health.max - health.current );
+        if amount > 0 {
+            regen.accumulated -= amount as f32;
+            health.current += amount;
+            healed_events.send( Healed { target: id, amount, regeneration: true } );
+        }
+    }
+}
+
+fn pick_up_health(
+    mut commands: Commands,
+    mut heal_events: EventWriter<HealEvent>,
+    player: Query<(Entity, &GlobalTransform), (With<Player>, Without<Dead>)>,
+    pickups: Query<(Entity, &GlobalTransform, &HealthPickup)>,
+) {
+    let (player, player_transform) = match player.get_single() {
+        Ok( player ) => player,
+        Err(_) => return,
+    };
+
+    for (id, transform, pickup) in pickups.iter() {
+        if transform.translation().xy().distance( player_transform.translation().xy() ) <= pickup.radius {
+            heal_events.send( HealEvent { target: player, amount: pickup.amount, overheal: pickup.overheal } );
+            commands.entity(id).despawn_recursive();
+        }
+    }
+}
+
+/// Health of the pickups dropped by drop_health_pickup
+pub const DROPPED_HEALTH: i32 = 4;
+
+/// An OnDeath hook, that drops a health pickup where the entity died
+pub fn drop_health_pickup(commands: &mut Commands, event: &EntityDied) {
+    commands.spawn( (
+        SpriteBundle {
+            sprite: Sprite { color: Color::GREEN, custom_size: Some(Vec2::splat(4.)), ..default() },
+            transform: Transform::from_translation( event.position.extend(0.) ),
+            ..default()
+        },
+        HealthPickup { amount: DROPPED_HEALTH, radius: 6., overheal: false },
+    ));
+}
+
+/// Shows a popup over the entities that got healed, regeneration is too frequent to show
+fn spawn_heal_popups(
+    mut commands: Commands,
+    mut healed_events: EventReader<Healed>,
+    targets: Query<&GlobalTransform>,
+) {
+    for event in healed_events.read() {
+        if event.regeneration {
+            continue;
+        }
+        if let Ok( transform ) = targets.get( event.target ) {
+            commands.spawn((
+                Text2dBundle {
+                    text: Text::from_section( format!("+{}", event.amount), TextStyle { font_size: 10., color: Color::GREEN, ..default() } ),
+                    transform: Transform::from_translation( transform.translation() + Vec3::Z ),
+                    ..default()
+                },
+                HealPopup { time: 0. },
+            ));
+        }
+    }
+}
+
+/// Rises the popups up on the screen, level with the camera, and fades them out
+fn update_heal_popups(
+    time: Res<Time>,
+    mut commands: Commands,
+    camera: Query<&GlobalTransform, With<MainCamera>>,
+    mut popups: Query<(Entity, &mut Transform, &mut Text, &mut HealPopup)>,
+) {
+    let camera_rotation = camera.get_single().map_or( Quat::IDENTITY, |camera| camera.compute_transform().rotation );
+    let up = Quat::mul_vec3( camera_rotation, Vec3::Y );
+
+    for (id, mut transform, mut text, mut popup) in popups.iter_mut() {
+        popup.time += time.delta_seconds();
+        if popup.time >= HEAL_POPUP_DURATION {
+            commands.entity(id).despawn_recursive();
+            continue;
+        }
+        transform.rotation = camera_rotation;
+        transform.translation += up * HEAL_POPUP_RISE / HEAL_POPUP_DURATION * time.delta_seconds();
+        text.sections[0].style.color.set_a( 1. - popup.time / HEAL_POPUP_DURATION );
+    }
+}
+
+/// The current health after healing amount, up to max, or max + overheal_cap if it can overheal
+pub fn healed_health(health: &Health, amount: i32, overheal: bool) -> i32 {
+    let cap = if overheal { health.max + health.overheal_cap } else { health.max };
+    // Overhealed health isn't taken away by regular heals
+    return (health.current + amount).min( cap.max( health.current ) );
+}
+
+/// Adds the heals to Health, up to max, or max + overheal_cap for heals that can overheal
+fn apply_heals(
+    mut heal_events: EventReader<HealEvent>,
+    mut healed_events: EventWriter<Healed>,
+    mut targets: Query<&mut Health, Without<Dead>>,
+) {
+    for event in heal_events.read() {
+        let mut health = match targets.get_mut( event.target ) {
+            Ok( health ) => health,
+            Err(_) => continue,
+        };
+
+        let healed = healed_health( &health, event.amount, event.overheal );
+        let amount = healed - health.current;
+        if amount > 0 {
+            health.current = healed;
+            healed_events.send( Healed { target: event.target, amount, regeneration: false } );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn health(current: i32) -> Health {
+        Health { max: 10, current, overheal_cap: 5, ..default() }
+    }
+
+    #[test]
+    fn heals_up_to_max() {
+        assert_eq!(healed_health( &health(4), 3, false ), 7);
+        assert_eq!(healed_health( &health(8), 5, false ), 10);
+    }
+
+    #[test]
+    fn overheal_goes_up_to_the_cap() {
+        assert_eq!(healed_health( &health(8), 5, true ), 13);
+        assert_eq!(healed_health( &health(8), 50, true ), 15);
+    }
+
+    #[test]
+    fn regular_heals_keep_overhealed_health() {
+        assert_eq!(healed_health( &health(13), 5, false ), 13);
+        assert_eq!(healed_health( &health(15), 5, true ), 15);
+    }
+
+    #[test]
+    fn regeneration_scales_with_vitality() {
+        assert_eq!(Regeneration::new(0).per_second(), 1.);
+        assert!((Regeneration::new(50).per_second() - 7.).abs() < 1e-5);
+    }
+}
@@ -1,6 +1,8 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, sprite::Anchor, window::PrimaryWindow};
 
-/// Marks entities with Text that are the child of Health component entities
+use crate::{death::Dead, editor_camera::MainCamera, input::Keybinds};
+
+/// Marks the "current/max" label in the HealthBar of Health component entities
 #[derive(Component)]
 pub struct HealthText;
 
@@ -10,6 +12,10 @@ pub struct HealthText;
 pub struct Health {
     pub max: i32,
     pub current: i32,
+    /// Flat reduction of every hit, that isn't true damage or armor piercing
+    pub defense: i32,
+    /// How far above max heals that allow overheal can go
+    pub overheal_cap: i32,
 } 
 
 
@@ -18,6 +24,8 @@ impl Default for Health {
         Health {
             max: 10,
             current: 5,
+            defense: 0,
+            overheal_cap: 0,
         }
     }
 }
@@ -25,44 +33,280 @@ pub struct HealthPlugin;
 
 impl Plugin for HealthPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, create_health_text);
-        app.add_systems(FixedUpdate, update_health_text);
+        app.init_resource::<HealthDisplay>();
+        app.add_systems(Update, 
+            (
+                toggle_health_display,
+                create_health_bars,
+                update_health_bars,
+                update_health_bar_transforms,
+                update_health_text,
+            ).chain()
+        );
+    }
+}
+
+pub const BAR_WIDTH: f32 = 16.;
+pub const BAR_HEIGHT: f32 = 2.;
+/// Seconds the damage trail waits before it starts shrinking, and how much of the bar it shrinks per second
+pub const TRAIL_DELAY: f32 = 0.3;
+pub const TRAIL_SPEED: f32 = 1.;
+/// Used for the placement of the bar, until the image of the sprite is loaded
+const FALLBACK_SPRITE_SIZE: Vec2 = Vec2::new(8., 8.);
+
+/// How health is shown above entities
+#[derive(Clone, Copy, PartialEq)]
+pub enum HealthDisplayMode {
+    Bars,
+    /// The "current/max" label, for debugging
+    Text,
+}
+
+#[derive(Resource)]
+pub struct HealthDisplay {
+    pub mode: HealthDisplayMode,
+}
+
+impl Default for HealthDisplay {
+    fn default() -> Self {
+        HealthDisplay { mode: HealthDisplayMode::Bars }
+    }
+}
+
+/// The root of a health bar, a child of the Health entity \
+/// It only shows when the entity is damaged, or the mouse is over it
+#[derive(Component)]
+pub struct HealthBar {
+    /// The part of the bar the damage trail covers
+    pub trail: f32,
+    /// Seconds until the trail starts shrinking
+    pub trail_delay: f32,
+}
+
+/// Marks the background, fill, and damage trail sprites of a HealthBar
+#[derive(Component)]
+pub struct HealthBarBackground;
+
+#[derive(Component)]
+pub struct HealthBarFill;
+
+#[derive(Component)]
+pub struct HealthBarTrail;
+
+fn toggle_health_display(
+    input: Res<Keybinds>,
+    mut display: ResMut<HealthDisplay>,
+) {
+    if input.toggle_health_display.active {
+        display.mode = match display.mode {
+            HealthDisplayMode::Bars => HealthDisplayMode::Text,
+            HealthDisplayMode::Text => HealthDisplayMode::Bars,
+        };
     }
 }
 
 /// Checks for entities that got a Health component \
-/// If they got one, we place a text component on them as a child, with a HealthText component
-fn create_health_text(
+/// If they got one, we place a health bar on them as a child, with the HealthText label in it
+fn create_health_bars(
     mut commands: Commands,
-    mut health_event: Query<(Entity, &Handle<Image>), (Added<Health>, With<Sprite>)>   ,
-    assets: Res<Assets<Image>>
+    health_event: Query<Entity, (Added<Health>, With<Sprite>)>,
 ) {
-    for (entity, sprite) in health_event.iter_mut() {
-        let sprite_size = assets.get(sprite.id()).unwrap().size_f32();
-        let text = commands.spawn( 
+    for entity in health_event.iter() {
+        let bar = commands.spawn( 
             (
-            Text2dBundle {
-                text: Text::from_section( "None", TextStyle { font_size: 10., ..default()}),
-                transform: Transform::from_translation( Vec3::new(0., -sprite_size.y, 0.)),
-                ..default()
-            },
-            HealthText
+                SpatialBundle::default(),
+                HealthBar { trail: 1., trail_delay: 0. },
             )
-        ).id();
-        commands.entity(entity).add_child(text);
+        ).with_children( |bar| {
+            let segment = |color: Color, z: f32| SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some( Vec2::new(BAR_WIDTH, BAR_HEIGHT) ),
+                    anchor: Anchor::CenterLeft,
+                    ..default()
+                },
+                transform: Transform::from_translation( Vec3::new(-BAR_WIDTH / 2., 0., z) ),
+                ..default()
+            };
+            bar.spawn( (segment( Color::rgb(0.1, 0.1, 0.1), 0. ), HealthBarBackground) );
+            bar.spawn( (segment( Color::rgb(0.9, 0.8, 0.3), 0.1 ), HealthBarTrail) );
+            bar.spawn( (segment( Color::rgb(0.2, 0.8, 0.2), 0.2 ), HealthBarFill) );
+            bar.spawn( 
+                (
+                    Text2dBundle {
+                        text: Text::from_section( "None", TextStyle { font_size: 10., ..default()}),
+                        transform: Transform::from_translation( Vec3::new(0., 0., 0.3) ),
+                        ..default()
+                    },
+                    HealthText
+                )
+            );
+        }).id();
+        commands.entity(entity).add_child(bar);
     }
 }
 
+/// Sizes the fill and the trail of every bar by the health of its entity, and hides the bars that shouldn't show \
+/// In HealthDisplayMode::Text only the label of the bar shows
+fn update_health_bars(
+    time: Res<Time>,
+    display: Res<HealthDisplay>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    mut bars: Query<(&mut HealthBar, &mut Visibility, &Parent, &Children)>,
+    mut segments: Query<
+        (&mut Sprite, &mut Visibility, Has<HealthBarFill>, Has<HealthBarTrail>), 
+        (Or<(With<HealthBarBackground>, With<HealthBarFill>, With<HealthBarTrail>)>, Without<Health>, Without<HealthBar>)
+    >,
+    entities: Query<(&Health, &GlobalTransform, &Sprite, &Handle<Image>, Has<Dead>)>,
+    images: Res<Assets<Image>>,
+) {
+    // The mouse in the world, none if it's outside the window
+    let cursor = match (window.get_single(), camera.get_single()) {
+        (Ok( window ), Ok( (camera, cam_transform) )) => window.cursor_position()
+            .and_then( |pos| camera.viewport_to_world_2d(cam_transform, pos) ),
+        _ => None,
+    };
+
+    for (mut bar, mut visibility, parent, children) in bars.iter_mut() {
+        let (health, transform, sprite, image, dead) = match entities.get( parent.get() ) {
+            Ok( entity ) => entity,
+            Err(_) => continue,
+        };
+        let fraction = (health.current as f32 / health.max.max(1) as f32).clamp(0., 1.);
+
+        // The trail follows the fill down after a delay, and jumps up with it when healed
+        if fraction >= bar.trail {
+            bar.trail = fraction;
+            bar.trail_delay = TRAIL_DELAY;
+        } else if bar.trail_delay > 0. {
+            bar.trail_delay -= time.delta_seconds();
+        } else {
+            bar.trail = (bar.trail - TRAIL_SPEED * time.delta_seconds()).max(fraction);
+        }
 
-/// For every HealthText, get their text, and their parent, and update the shown health
+        let text_mode = display.mode == HealthDisplayMode::Text;
+        for &child in children.iter() {
+            if let Ok( (mut segment, mut segment_visibility, is_fill, is_trail) ) = segments.get_mut(child) {
+                *segment_visibility = if text_mode { Visibility::Hidden } else { Visibility::Inherited };
+                let width = if is_fill { fraction } else if is_trail { bar.trail } else { 1. };
+                segment.custom_size = Some( Vec2::new(BAR_WIDTH * width, BAR_HEIGHT) );
+            }
+        }
+
+        let hovered = cursor.is_some_and( |cursor| {
+            let size = sprite_size(sprite, image, &images);
+            let local = transform.affine().inverse().transform_point3( cursor.extend(0.) ).xy();
+            local.x.abs() <= size.x / 2. && local.y.abs() <= size.y / 2.
+        });
+        let shown = text_mode
+            || (!dead && (health.current < health.max || hovered));
+        *visibility = if shown { Visibility::Inherited } else { Visibility::Hidden };
+    }
+}
+
+/// Keeps the bars under their entity and level, from the view of the camera, whichever way the entity is turned
+fn update_health_bar_transforms(
+    camera: Query<&GlobalTransform, With<MainCamera>>,
+    mut bars: Query<(&mut Transform, &Parent), With<HealthBar>>,
+    entities: Query<(&GlobalTransform, &Sprite, &Handle<Image>)>,
+    images: Res<Assets<Image>>,
+) {
+    let camera_rotation = match camera.get_single() {
+        Ok( camera ) => camera.compute_transform().rotation,
+        Err(_) => return,
+    };
+
+    for (mut transform, parent) in bars.iter_mut() {
+        if let Ok( (parent_transform, sprite, image) ) = entities.get( parent.get() ) {
+            let size = sprite_size(sprite, image, &images);
+            let parent_rotation = parent_transform.compute_transform().rotation;
+            // Below the sprite on the screen, turned into the space of the entity
+            let offset = Quat::mul_vec3( camera_rotation, Vec3::new(0., -(size.y / 2. + BAR_HEIGHT * 2.), 1.) );
+            transform.translation = Quat::mul_vec3( parent_rotation.inverse(), offset );
+            transform.rotation = parent_rotation.inverse() * camera_rotation;
+        }
+    }
+}
+
+/// The size the sprite is drawn at, images that aren't loaded yet use FALLBACK_SPRITE_SIZE
+fn sprite_size(sprite: &Sprite, image: &Handle<Image>, images: &Assets<Image>) -> Vec2 {
+    return sprite.custom_size
+        .or_else( || images.get(image).map( |image| image.size_f32() ) )
+        .unwrap_or( FALLBACK_SPRITE_SIZE );
+}
+
+/// For every HealthText, update the shown health, only shown in HealthDisplayMode::Text
 fn update_health_text(
-    mut text: Query<(&mut Text, &Parent), With<HealthText>>,
+    display: Res<HealthDisplay>,
+    mut text: Query<(&mut Text, &mut Visibility, &Parent), With<HealthText>>,
+    bars: Query<&Parent, With<HealthBar>>,
     health_entities: Query<&Health>
 ) {
-    for (mut text, parent) in text.iter_mut() {
-        if let Ok(health) = health_entities.get(parent.get()) {
+    for (mut text, mut visibility, parent) in text.iter_mut() {
+        *visibility = match display.mode {
+            HealthDisplayMode::Text => Visibility::Inherited,
+            HealthDisplayMode::Bars => Visibility::Hidden,
+        };
+
+        let health = bars.get( parent.get() ).and_then( |bar| health_entities.get( bar.get() ) );
+        if let Ok(health) = health {
             text.sections[0].value = format!("{}/{}", health.current, health.max); 
         }
-    
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn health_bar_app() -> App {
+        let mut app = App::new();
+        app.add_plugins( (MinimalPlugins, HierarchyPlugin, AssetPlugin::default()) );
+        app.init_asset::<Image>();
+        app.init_resource::<HealthDisplay>();
+        app.add_systems(Update, (create_health_bars, update_health_bars, update_health_text).chain());
+        return app;
+    }
+
+    fn fill_width(app: &mut App) -> f32 {
+        let mut fills = app.world.query_filtered::<&Sprite, With<HealthBarFill>>();
+        return fills.single(&app.world).custom_size.unwrap().x;
+    }
+
+    #[test]
+    fn bar_follows_the_health_of_its_owner() {
+        let mut app = health_bar_app();
+        let owner = app.world.spawn( (SpriteBundle::default(), Health { max: 10, current: 10, ..default() }) ).id();
+
+        // Created with the Health, and hidden while the owner is at full health
+        app.update();
+        app.update();
+        let mut bars = app.world.query_filtered::<(&Parent, &Visibility), With<HealthBar>>();
+        let (parent, visibility) = bars.single(&app.world);
+        assert_eq!(parent.get(), owner);
+        assert_eq!(*visibility, Visibility::Hidden);
+        assert_eq!(fill_width(&mut app), BAR_WIDTH);
+
+        app.world.get_mut::<Health>(owner).unwrap().current = 4;
+        app.update();
+        assert_eq!(*bars.single(&app.world).1, Visibility::Inherited);
+        assert!((fill_width(&mut app) - BAR_WIDTH * 0.4).abs() < 1e-5);
+        let mut texts = app.world.query_filtered::<&Text, With<HealthText>>();
+        assert_eq!(texts.single(&app.world).sections[0].value, "4/10");
+    }
+
+    #[test]
+    fn bar_is_removed_with_its_owner() {
+        let mut app = health_bar_app();
+        let owner = app.world.spawn( (SpriteBundle::default(), Health::default()) ).id();
+        app.update();
+
+        let mut parts = app.world.query_filtered::<Entity, Or<(With<HealthBar>, With<HealthBarFill>, With<HealthBarTrail>, With<HealthBarBackground>, With<HealthText>)>>();
+        assert_eq!(parts.iter(&app.world).count(), 5);
+
+        app.world.entity_mut(owner).despawn_recursive();
+        app.update();
+        assert_eq!(parts.iter(&app.world).count(), 0);
+    }
+}
@@ -0,0 +1,181 @@
+use bevy::{prelude::*, utils::HashSet};
+
+use crate::{
+    damage::{DamageApplied, DamageQueue, DamageStage},
+    death::Dead,
+    states::AppSet,
+    status::filter_invulnerable_status
+};
+
+pub struct HitPlugin;
+
+impl Plugin for HitPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PostUpdate,
+            (
+                // Hits the Invulnerable status drops shouldn't start the invulnerability frames
+                filter_invulnerable.in_set(DamageStage::Invulnerability).after(filter_invulnerable_status),
+                start_hit_reactions.in_set(DamageStage::PostHit),
+            )
+        );
+        app.add_systems(Update,
+            (
+                update_invulnerability,
+                update_hit_flash,
+                update_knockback,
+            ).in_set(AppSet::Gameplay)
+        );
+    }
+}
+
+/// After taking damage, the entity ignores every other hit for duration seconds
+#[derive(Component)]
+pub struct Invulnerability {
+    pub duration: f32,
+    /// Seconds until the entity can be hit again
+    pub remaining: f32,
+}
+
+impl Invulnerability {
+    pub fn new(duration: f32) -> Self {
+        Invulnerability { duration, remaining: 0. }
+    }
+}
+
+/// Tints the sprite of the entity for duration seconds after taking damage
+#[derive(Component)]
+pub struct HitFlash {
+    pub color: Color,
+    pub duration: f32,
+    pub remaining: f32,
+    /// The color of the sprite before the flash, set back when it ends
+    base_color: Option<Color>,
+}
+
+impl HitFlash {
+    pub fn new(color: Color, duration: f32) -> Self {
+        HitFlash { color, duration, remaining: 0., base_color: None }
+    }
+}
+
+/// Pushes the entity away from what hit it
+#[derive(Component)]
+pub struct Knockback {
+    /// Starting speed of the push, in pixels per second
+    pub strength: f32,
+    /// How fast the push slows down, the velocity is multiplied by exp(-drag * delta) every frame
+    pub drag: f32,
+    pub velocity: Vec2,
+}
+
+impl Knockback {
+    pub fn new(strength: f32, drag: f32) -> Self {
+        Knockback { strength, drag, velocity: Vec2::ZERO }
+    }
+}
+
+/// Removes the damage against entities that are invulnerable,
+/// and makes the ones that get hit invulnerable
+fn filter_invulnerable(
+    mut queue: ResMut<DamageQueue>,
+    mut targets: Query<&mut Invulnerability>,
+) {
+    // Only the first hit of a frame goes through
+    let mut hit = HashSet::new();
+
+    queue.events.retain( |event| {
+        if event.periodic {
+            return true;
+        }
+        let mut invulnerability = match targets.get_mut( event.target ) {
+            Ok( invulnerability ) => invulnerability,
+            Err(_) => return true,
+        };
+        if invulnerability.remaining > 0. || hit.contains( &event.target ) {
+            return false;
+        }
+        if event.amount > 0 {
+            hit.insert( event.target );
+            invulnerability.remaining = invulnerability.duration;
+        }
+        return true;
+    });
+}
+
+fn start_hit_reactions(
+    mut applied_events: EventReader<DamageApplied>,
+    positions: Query<&GlobalTransform>,
+    mut flashes: Query<(&mut HitFlash, &Sprite), Without<Dead>>,
+    mut knockbacks: Query<(&mut Knockback, &GlobalTransform), Without<Dead>>,
+) {
+    for event in applied_events.read() {
+        if event.amount <= 0 || event.periodic {
+            continue;
+        }
+
+        if let Ok( (mut flash, sprite) ) = flashes.get_mut( event.target ) {
+            if flash.base_color.is_none() {
+                flash.base_color = Some( sprite.color );
+            }
+            flash.remaining = flash.duration;
+        }
+
+        if let Ok( (mut knockback, transform) ) = knockbacks.get_mut( event.target ) {
+            // Push away from the projectile, or from the attacker if the projectile is gone
+            let from = event.source.and_then( |source| positions.get(source).ok() )
+                .or_else( || event.attacker.and_then( |attacker| positions.get(attacker).ok() ) );
+            if let Some( from ) = from {
+                let dir = (transform.translation().xy() - from.translation().xy()).normalize_or_zero();
+                let strength = knockback.strength;
+                knockback.velocity += dir * strength;
+            }
+        }
+    }
+}
+
+fn update_invulnerability(
+    time: Res<Time>,
+    mut entities: Query<&mut Invulnerability>,
+) {
+    for mut invulnerability in entities.iter_mut() {
+        invulnerability.remaining = (invulnerability.remaining - time.delta_seconds()).max(0.);
+    }
+}
+
+fn update_hit_flash(
+    time: Res<Time>,
+    mut entities: Query<(&mut HitFlash, &mut Sprite), Without<Dead>>,
+) {
+    for (mut flash, mut sprite) in entities.iter_mut() {
+        let base_color = match flash.base_color {
+            Some( color ) => color,
+            None => continue,
+        };
+
+        flash.remaining -= time.delta_seconds();
+        if flash.remaining > 0. {
+            sprite.color = flash.color;
+        } else {
+            sprite.color = base_color;
+            flash.base_color = None;
+        }
+    }
+}
+
+fn update_knockback(
+    time: Res<Time>,
+    mut entities: Query<(&mut Knockback, &mut Transform), Without<Dead>>,
+) {
+    let delta = time.delta_seconds();
+    for (mut knockback, mut transform) in entities.iter_mut() {
+        if knockback.velocity == Vec2::ZERO {
+            continue;
+        }
+        transform.translation += (knockback.velocity * delta).extend(0.);
+        let drag = knockback.drag;
+        knockback.velocity *= (-drag * delta).exp();
+        if knockback.velocity.length_squared() < 1. {
+            knockback.velocity = Vec2::ZERO;
+        }
+    }
+}
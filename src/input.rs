@@ -93,6 +93,7 @@ pub struct Keybinds {
     pub camera_reset: Key,
 
     pub change_state: Key,
+    pub toggle_health_display: Key,
 
     pub scroll_wheel: f32,
 }
@@ -120,6 +121,7 @@ impl Plugin for InputPlugin {
                 camera_reset: Key::keyboard(KeyCode::KeyR),
 
                 change_state: Key::keyboard(KeyCode::Tab),
+                toggle_health_display: Key::keyboard(KeyCode::F3),
 
                 scroll_wheel: 0.,
             });
@@ -162,6 +164,7 @@ fn input_manager(
 
 
     keybinds.change_state.just_pressed( &system );
+    keybinds.toggle_health_display.just_pressed( &system );
 }
 
 fn mouse_wheel(
@@ -10,12 +10,30 @@ mod editor;
 mod rapier;
 mod stateful;
 mod editor_camera;
+mod bulletpattern;
+mod emitter;
+mod pool;
+mod wall;
+mod damage;
+mod death;
+mod hit;
+mod status;
+mod heal;
+mod enemy;
+#[cfg(feature = "bench")]
+mod bench;
 
 use bevy::prelude::*;
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use crate::states::StateManager;
 
 fn main() {
+    #[cfg(feature = "bench")]
+    if std::env::args().any( |arg| arg == "--bench-projectiles" ) {
+        bench::run();
+        return;
+    }
+
     App::new()
         .add_plugins(DefaultPlugins.set(ImagePlugin::default_nearest()))
         .insert_resource(Msaa::Off)
@@ -1,7 +1,7 @@
 use bevy::{prelude::*, window::PrimaryWindow};
 use bevy_rapier2d::prelude::*;
 use crate::{
-    editor_camera::MainCamera, entity::EntityRotate, health::Health, input::Keybinds, projectile::{ProjectileAsset, ProjectileTargetingType}, projectilepattern::{CirclePattern, IPPattern}, states::AppSet, weapon::Weapon
+    bulletpattern::BulletPattern, editor_camera::MainCamera, enemy::Enemy, entity::EntityRotate, heal::{HealthPickup, Regeneration}, health::Health, hit::{HitFlash, Invulnerability}, input::Keybinds, rapier::player_groups, states::AppSet, status::StatusEffects, wall::WallBundle, weapon::{Weapon, WeaponDefinition}
 };
 
 pub struct PlayerPlugin;
@@ -13,7 +13,8 @@ impl Plugin for PlayerPlugin {
             (
                 update_player_transform,
                 update_player_camera,
-                update_weapon
+                update_weapon,
+                update_weapon_text,
             ).in_set(AppSet::Gameplay)
         );
     }
@@ -27,20 +28,35 @@ pub struct Player {
     // All set by the input system
     pub movement_speed: f32,
 
+    /// Multiplies the damage of the weapon, 25 deals the damage of the weapon as is
+    pub attack: i32,
+
     pub camera_rot_speed: f32,
 }
 
 impl Default for Player {
     fn default() -> Self {
         Player {
-            weapon: Some(Weapon::default()),
+            weapon: None,
 
             movement_speed: 100.,
+            attack: 25,
             camera_rot_speed: 3.,
         }
     }
 }
 
+/// Marks the label that shows the weapon of the player
+#[derive(Component)]
+pub struct WeaponText;
+
+impl Player {
+    /// The damage multiplier from attack, the same formula as RotMG
+    pub fn attack_multiplier(&self) -> f32 {
+        return 0.5 + self.attack as f32 / 50.;
+    }
+}
+
 /// This is where we currently spawn the camera, player, 
 /// and the other health entities
 fn setup(
@@ -59,20 +75,21 @@ fn setup(
         SpriteBundle {
         texture: char.clone(),
         ..default()},
-        Player::default(),
-        Health::default(),
+        Player {
+            weapon: Some(Weapon::new( asset_server.load("weapons/starter_staff.weapon.ron") )),
+            ..default()
+        },
+        Health { overheal_cap: 5, ..default() },
         RigidBody::KinematicPositionBased,
-        Collider::cuboid(2., 2.)
+        Collider::cuboid(2., 2.),
+        player_groups(),
+        Invulnerability::new(0.2),
+        HitFlash::new(Color::RED, 0.1),
+        Regeneration::new(0),
     )).id();
 
     commands.entity(player_id).add_child(cam_id);
-    commands.spawn( ( 
-        SpriteBundle { texture: char.clone(), ..default()},
-        Health::default(),
-        EntityRotate,
-        RigidBody::KinematicPositionBased,
-        Collider::cuboid(5., 5.)
-    ));
+    Enemy::spawn( &mut commands, asset_server.load("enemies/dummy.enemy.ron"), Transform::default() );
     commands.spawn( ( 
         SpriteBundle { 
             texture: char,
@@ -81,17 +98,39 @@ fn setup(
         Health::default(),
         EntityRotate
     ));
+    commands.spawn( (
+        SpriteBundle {
+            sprite: Sprite { color: Color::GREEN, custom_size: Some(Vec2::splat(4.)), ..default() },
+            transform: Transform::from_translation(Vec3::new(-30., 0., 0.)),
+            ..default()
+        },
+        HealthPickup { amount: 8, radius: 6., overheal: true },
+    ));
+    commands.spawn( (
+        TextBundle::from_section( "", TextStyle { font_size: 16., ..default() } )
+            .with_style( Style {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(8.),
+                left: Val::Px(8.),
+                ..default()
+            }),
+        WeaponText,
+    ));
+    commands.spawn( WallBundle::new( Transform::from_translation(Vec3::new(0., 60., 0.)), Vec2::new(40., 4.) ) );
 }
 
 
 fn update_weapon(
     time: Res<Time>,
     input: Res<Keybinds>,
-    commands: Commands,
+    mut commands: Commands,
     window: Query<&Window, With<PrimaryWindow>>,
     camera: Query< (&Camera, &GlobalTransform)>,
-    projectile_asset: Res<ProjectileAsset>,
-    mut player: Query<(&mut Player, &Transform)>,
+    weapons: Res<Assets<WeaponDefinition>>,
+    patterns: Res<Assets<BulletPattern>>,
+    mut weapon_events: EventReader<AssetEvent<WeaponDefinition>>,
+    mut pattern_events: EventReader<AssetEvent<BulletPattern>>,
+    mut player: Query<(Entity, &mut Player, &Transform, Option<&StatusEffects>)>,
 ) {
     let p = player.get_single_mut();
 
@@ -100,7 +139,7 @@ fn update_weapon(
         return;
     }
 
-    let (mut player, transform) = p.unwrap();
+    let (player_id, mut player, transform, statuses) = p.unwrap();
 
     // The reason I implemented weapon this way, 
     // is i want the weapon cooldown to decrement
@@ -109,12 +148,29 @@ fn update_weapon(
         // cache out the firing, because we request weapon as mutable later
         let firing = input.weapon_fire.active;
         let time = time.delta().as_secs_f32();
+        let attack_multiplier = player.attack_multiplier();
         let wp = player.weapon.as_mut().unwrap();
 
-        wp.increment_attack_timer( time );
+        // The definition is none while its file is still loading
+        let definition = match weapons.get( &wp.definition ) {
+            Some( definition ) => definition,
+            None => return,
+        };
+        let cooldown = definition.cooldown() / statuses.map_or( 1., |s| s.fire_rate_multiplier() );
+
+        wp.increment_attack_timer( time, cooldown );
+
+        // Rebuild the pattern if the weapon or its pattern file changed
+        let weapon_changed = weapon_events.read()
+            .filter( |event| event.is_modified( wp.definition.id() ) ).count() > 0;
+        let pattern_changed = pattern_events.read()
+            .filter( |event| event.is_modified( definition.pattern_handle.id() ) ).count() > 0;
+        if weapon_changed || pattern_changed {
+            wp.active_pattern = None;
+        }
 
         // * firing - updated from attack system
-        if firing && wp.can_attack() {
+        if firing && wp.can_attack( cooldown ) {
             // Main window, we only have a single one
             let window = window.single(); 
             let (camera, cam_transform) = camera.single();
@@ -123,35 +179,64 @@ fn update_weapon(
             let mouse_world = window.cursor_position()
             .and_then( |pos| camera.viewport_to_world_2d(cam_transform, pos ));
 
+            // The pattern is none while its file is still loading
+            let bullet_pattern = patterns.get( &definition.pattern_handle );
+
             // mouse_world is none if our mouse is outside the window
-            if mouse_world.is_some() {
+            if mouse_world.is_some() && bullet_pattern.is_some() {
                 // Get vector pointing in the direction of the 
                 // mouse from the player
                 let dir = (mouse_world.unwrap() - transform.translation.xy())
                     .normalize_or_zero();
                 
-                let mut pattern = CirclePattern {
-                    amount: 10,
-                    dir,
-                    targeting: ProjectileTargetingType::PLAYER,
-                    ..default()
-                };
-                let handle = projectile_asset.handle.clone();
-                pattern.spawn(commands, transform, handle);
+                let bullet_pattern = bullet_pattern.unwrap();
+                let source = definition.pattern_handle.clone();
+                let weapon_handle = wp.definition.clone();
+                let pattern = wp.active_pattern
+                    .get_or_insert_with( || bullet_pattern.build(dir, Some(source)) );
+                pattern.set_dir(dir);
+                // Every shot uses the current damage, so weapon and attack changes apply right away
+                let damage = definition.damage.scaled( attack_multiplier );
+                pattern.for_each_template( &mut |template| {
+                    definition.apply_to(template, damage, player_id);
+                    template.weapon = Some( weapon_handle.clone() );
+                });
+
+                let sprite = definition.sprite_handle.clone()
+                    .unwrap_or_else( || bullet_pattern.sprite_handle.clone() );
+                pattern.spawn(&mut commands, transform, sprite);
             }
         }
     }
 
 }
 
+/// Shows the name, tier, and class of the weapon the player holds, once its file is loaded
+fn update_weapon_text(
+    weapons: Res<Assets<WeaponDefinition>>,
+    player: Query<&Player>,
+    mut text: Query<&mut Text, With<WeaponText>>,
+) {
+    let label = player.get_single().ok()
+        .and_then( |player| player.weapon.as_ref() )
+        .and_then( |weapon| weapons.get( &weapon.definition ) )
+        .map_or( String::new(), |definition| definition.label() );
+
+    for mut text in text.iter_mut() {
+        if text.sections[0].value != label {
+            text.sections[0].value = label.clone();
+        }
+    }
+}
+
 fn update_player_transform(
-    mut player: Query<(&mut Transform, &Player)>,
+    mut player: Query<(&mut Transform, &Player, Option<&StatusEffects>)>,
     time: Res<Time>,
     input: Res<Keybinds>
 ) {
     // Errors if we have zero or multiple players
     match player.get_single_mut() {
-        Ok( (mut transform, player) ) => {
+        Ok( (mut transform, player, statuses) ) => {
 
             let mut movement_vec = Vec2::default();
 
@@ -163,8 +248,9 @@ fn update_player_transform(
             movement_vec = movement_vec.normalize_or_zero();
             // As we are rotating the player, 
             // our "UP" direction changes, we should reflect that in our movement
+            let speed = player.movement_speed * statuses.map_or( 1., |s| s.speed_multiplier() );
             let movement = 
-                (Vec2::splat(player.movement_speed * time.delta_seconds()) * movement_vec)
+                (Vec2::splat(speed * time.delta_seconds()) * movement_vec)
                     .extend(0.);
 
             let rotation = transform.rotation;
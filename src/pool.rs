@@ -0,0 +1,306 @@
+use bevy::{ecs::system::Command, prelude::*};
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    bulletpattern::{BulletPattern, PatternSource},
+    projectile::{PState, Projectile},
+    projectilepattern::{ProjectileTemplate, SpawnOnFinish},
+    rapier::projectile_groups,
+    stateful::Stateful,
+    states::AppSet,
+    weapon::WeaponSource
+};
+
+pub struct ProjectilePoolPlugin;
+
+impl Plugin for ProjectilePoolPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ProjectilePool>();
+        app.add_systems(Update, log_pool_stats.in_set(AppSet::Gameplay));
+    }
+}
+
+/// Marks projectiles that go back into the pool instead of despawning
+#[derive(Component)]
+pub struct Pooled;
+
+/// Keeps despawned projectiles around, hidden and with their collider disabled,
+/// and reuses them for new projectiles \
+/// This way firing thousands of bullets doesn't spawn and despawn thousands of entities,
+/// use `Commands::spawn_projectile` and `Commands::despawn_projectile` so it works
+#[derive(Resource)]
+pub struct ProjectilePool {
+    /// Without it projectiles are spawned and despawned like any other entity
+    pub enabled: bool,
+    /// The most projectiles kept in the pool, the ones over it are despawned
+    pub capacity: usize,
+    /// Logs the stats every this many seconds, None doesn't log
+    pub log_interval: Option<f32>,
+    pub stats: PoolStats,
+
+    free: Vec<Entity>,
+}
+
+impl Default for ProjectilePool {
+    fn default() -> Self {
+        ProjectilePool {
+            enabled: true,
+            capacity: 20000,
+            log_interval: None,
+            stats: PoolStats::default(),
+            free: Vec::new(),
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy, Debug)]
+pub struct PoolStats {
+    /// Projectiles currently flying
+    pub active: usize,
+    /// The most projectiles that were flying at once
+    pub peak_active: usize,
+    /// Projectiles waiting in the pool
+    pub free: usize,
+    /// Projectiles spawned as new entities
+    pub created: u64,
+    /// Projectiles taken from the pool
+    pub reused: u64,
+    /// Projectiles put back into the pool
+    pub released: u64,
+    /// Projectiles that got despawned, because the pool was full or disabled
+    pub despawned: u64,
+}
+
+pub trait ProjectileCommands {
+    /// Spawns a projectile, reusing one from the pool if there is any
+    fn spawn_projectile(&mut self, template: &ProjectileTemplate, transform: Transform, sprite: Handle<Image>);
+    /// Puts the projectile back into the pool, or despawns it if it can't
+    fn despawn_projectile(&mut self, entity: Entity);
+}
+
+impl ProjectileCommands for Commands<'_, '_> {
+    fn spawn_projectile(&mut self, template: &ProjectileTemplate, transform: Transform, sprite: Handle<Image>) {
+        self.add( SpawnProjectile {
+            template: template.clone(),
+            transform,
+            sprite,
+        });
+    }
+
+    fn despawn_projectile(&mut self, entity: Entity) {
+        self.add( DespawnProjectile(entity) );
+    }
+}
+
+struct SpawnProjectile {
+    template: ProjectileTemplate,
+    transform: Transform,
+    sprite: Handle<Image>,
+}
+
+impl Command for SpawnProjectile {
+    fn apply(self, world: &mut World) {
+        let (enabled, reused) = {
+            let mut pool = world.resource_mut::<ProjectilePool>();
+            let reused = if pool.enabled { pool.free.pop() } else { None };
+            (pool.enabled, reused)
+        };
+        // The entity could have been despawned by something else while it was in the pool
+        let reused = reused.filter( |id| world.get_entity(*id).is_some() );
+
+        let id = match reused {
+            Some( id ) => {
+                world.entity_mut(id)
+                    .insert((
+                        self.transform,
+                        GlobalTransform::from(self.transform),
+                        self.sprite,
+                        Visibility::Inherited,
+                    ))
+                    .remove::<ColliderDisabled>();
+                id
+            },
+            None => {
+                let mut entity = world.spawn(
+                    SpriteBundle {
+                        transform: self.transform,
+                        texture: self.sprite,
+                        ..default()
+                    }
+                );
+                if enabled {
+                    entity.insert( Pooled );
+                }
+                entity.id()
+            }
+        };
+
+        let mut template = self.template;
+        if let Some( damage ) = template.damage_range {
+            template.base.damage = damage.roll();
+        }
+
+        // The pattern is none while its file is still loading, then the projectile just doesn't spawn it
+        let spawn_on_finish = template.spawn_on_finish.as_ref()
+            .and_then( |handle| Some( (handle, world.resource::<Assets<BulletPattern>>().get(handle)?) ) )
+            .map( |(handle, pattern)| {
+                let mut spawned = pattern.build( Vec2::Y, Some( handle.clone() ) );
+                // The split projectiles belong to whoever fired this one
+                spawned.for_each_template( &mut |child| {
+                    child.base.targeting_type = template.base.targeting_type;
+                    child.base.owner = template.base.owner;
+                });
+                SpawnOnFinish { pattern: spawned, sprite: pattern.sprite_handle.clone() }
+            });
+
+        let mut entity = world.entity_mut(id);
+        // Every insert that adds components moves the entity to another archetype, so they're batched, 
+        // and reused projectiles keep their collider if it's the same size
+        let same_collider = entity.get::<Collider>()
+            .and_then( |collider| collider.as_cuboid() )
+            .is_some_and( |cuboid| cuboid.half_extents() == template.collider );
+        if !same_collider {
+            entity.insert( Collider::cuboid(template.collider.x, template.collider.y) );
+        }
+        let groups = projectile_groups( template.base.targeting_type );
+        if template.states.is_empty() {
+            entity.insert( (groups, template.base) ).remove::<Stateful<PState>>();
+        } else {
+            entity.insert((
+                groups,
+                template.base,
+                Stateful::from_states( template.states, template.state_repeat ).with_on_finish( template.on_finish ),
+            ));
+        }
+        match spawn_on_finish {
+            Some( spawn ) => { entity.insert( spawn ); },
+            None => { entity.remove::<SpawnOnFinish>(); },
+        }
+        match template.source {
+            Some( source ) => { entity.insert( PatternSource(source) ); },
+            None => { entity.remove::<PatternSource>(); },
+        }
+        match template.weapon {
+            Some( weapon ) => { entity.insert( WeaponSource(weapon) ); },
+            None => { entity.remove::<WeaponSource>(); },
+        }
+
+        let mut pool = world.resource_mut::<ProjectilePool>();
+        match reused {
+            Some(_) => pool.stats.reused += 1,
+            None => pool.stats.created += 1,
+        }
+        pool.stats.active += 1;
+        pool.stats.peak_active = pool.stats.peak_active.max( pool.stats.active );
+        pool.stats.free = pool.free.len();
+    }
+}
+
+struct DespawnProjectile(Entity);
+
+impl Command for DespawnProjectile {
+    fn apply(self, world: &mut World) {
+        let pooled = match world.get_entity(self.0) {
+            // Projectiles hitting multiple things in the same frame get despawned multiple times
+            Some( entity ) if entity.contains::<Projectile>() => entity.contains::<Pooled>(),
+            _ => return,
+        };
+
+        let mut pool = world.resource_mut::<ProjectilePool>();
+        pool.stats.active = pool.stats.active.saturating_sub(1);
+
+        if pooled && pool.enabled && pool.free.len() < pool.capacity {
+            pool.free.push(self.0);
+            pool.stats.released += 1;
+            pool.stats.free = pool.free.len();
+
+            world.entity_mut(self.0)
+                .remove::<(Projectile, Stateful<PState>, PatternSource, WeaponSource, SpawnOnFinish)>()
+                .insert((Visibility::Hidden, ColliderDisabled));
+        } else {
+            pool.stats.despawned += 1;
+            world.entity_mut(self.0).despawn_recursive();
+        }
+    }
+}
+
+fn log_pool_stats(
+    time: Res<Time>,
+    pool: Res<ProjectilePool>,
+    mut timer: Local<f32>,
+) {
+    if let Some( interval ) = pool.log_interval {
+        *timer += time.delta_seconds();
+        if *timer >= interval {
+            *timer = 0.;
+            info!("Projectile pool: {:?}", pool.stats);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Spawns the projectile, and returns the entity that got it
+    fn spawn(world: &mut World, template: &ProjectileTemplate) -> Entity {
+        let mut projectiles = world.query_filtered::<Entity, With<Projectile>>();
+        let before: Vec<Entity> = projectiles.iter(world).collect();
+        SpawnProjectile { template: template.clone(), transform: Transform::default(), sprite: Handle::default() }.apply(world);
+        return projectiles.iter(world).find( |id| !before.contains(id) ).unwrap();
+    }
+
+    #[test]
+    fn reuses_despawned_projectiles_with_fresh_state() {
+        let mut world = World::new();
+        world.init_resource::<ProjectilePool>();
+        world.init_resource::<Assets<BulletPattern>>();
+
+        let fired = ProjectileTemplate { weapon: Some( Handle::default() ), ..default() };
+        let first = spawn(&mut world, &fired);
+        assert!(world.entity(first).contains::<WeaponSource>());
+
+        // Fly for a while and hit something
+        world.get_mut::<Projectile>(first).unwrap().hit.push( Entity::PLACEHOLDER );
+        world.get_mut::<Stateful<PState>>(first).unwrap().update_state(2.5);
+        assert_ne!(world.get::<Stateful<PState>>(first).unwrap().state_current, 0);
+
+        DespawnProjectile(first).apply(&mut world);
+        let entity = world.entity(first);
+        assert!(!entity.contains::<Projectile>());
+        assert!(!entity.contains::<Stateful<PState>>());
+        assert!(entity.contains::<ColliderDisabled>());
+        assert_eq!(entity.get::<Visibility>(), Some(&Visibility::Hidden));
+
+        let second = spawn(&mut world, &ProjectileTemplate::default());
+        assert_eq!(second, first);
+        let entity = world.entity(second);
+        assert!(entity.get::<Projectile>().unwrap().hit.is_empty());
+        let stateful = entity.get::<Stateful<PState>>().unwrap();
+        assert_eq!(stateful.state_current, 0);
+        assert_eq!(stateful.state_time, 0.);
+        assert!(!stateful.state_entered);
+        assert!(!entity.contains::<WeaponSource>());
+        assert!(!entity.contains::<ColliderDisabled>());
+        assert_eq!(entity.get::<Visibility>(), Some(&Visibility::Inherited));
+
+        let stats = world.resource::<ProjectilePool>().stats;
+        assert_eq!((stats.created, stats.reused, stats.released, stats.active, stats.free), (1, 1, 1, 1, 0));
+    }
+
+    #[test]
+    fn despawns_when_the_pool_is_full() {
+        let mut world = World::new();
+        world.insert_resource( ProjectilePool { capacity: 0, ..default() } );
+        world.init_resource::<Assets<BulletPattern>>();
+
+        let id = spawn(&mut world, &ProjectileTemplate::default());
+        DespawnProjectile(id).apply(&mut world);
+        assert!(world.get_entity(id).is_none());
+        // Despawning it again does nothing
+        DespawnProjectile(id).apply(&mut world);
+
+        let stats = world.resource::<ProjectilePool>().stats;
+        assert_eq!((stats.despawned, stats.released, stats.active), (1, 0, 0));
+    }
+}
@@ -1,27 +1,71 @@
 use bevy::prelude::*;
-use bevy_rapier2d::{geometry::Collider, pipeline::QueryFilter, plugin::RapierContext};
+use serde::Deserialize;
+use bevy_rapier2d::{geometry::{Collider, CollisionGroups, Group}, pipeline::QueryFilter, plugin::RapierContext};
 use crate::{
+    damage::{DamageEvent, DamageKind, DamageStage},
+    death::{Dead, Fading},
+    editor_camera::MainCamera,
     health::Health, 
     player::Player, 
-    stateful::{State, StateDuration, Stateful, StatefulEvent}, states::AppSet
+    pool::ProjectileCommands,
+    status::{ApplyStatus, StatusEffect},
+    rapier::{ENEMY_GROUP, ENEMY_PROJECTILE_GROUP, PLAYER_GROUP, PLAYER_PROJECTILE_GROUP, WALL_GROUP},
+    projectilepattern::SpawnOnFinish,
+    stateful::{State, StateDuration, StateParam, StatefulFinished, StatefulPlugin, StatefulSet}, states::AppSet,
+    wall::Wall
 };
 
 pub struct ProjectilePlugin;
 
-#[derive(Clone, Copy)]
+/// A state of a projectile, parameters that are None keep their previous value
+#[derive(Clone, Copy, Deserialize)]
 pub struct PState {
-    pub angular_velocity: Option<f32>,
-    pub speed: Option<f32>,
+    pub angular_velocity: Option<StateParam>,
+    pub speed: Option<StateParam>,
     pub duration: StateDuration,
 }
 
 impl State for PState {
+    type Target = Projectile;
+
     fn get_duration(&self) -> StateDuration {
         self.duration
     }
+
+    fn enter(&self, p: &mut Projectile, t: &mut Transform) {
+        // Instant states take no time, so we apply them here once.
+        // Their angular velocity is a rotation in degrees, instead of degrees per second
+        if self.duration == StateDuration::Instant {
+            if let Some( speed ) = self.speed {
+                p.speed = speed.value(0.) + p.speed_offset;
+            }
+            if let Some( angle ) = self.angular_velocity {
+                t.rotate_z( angle.value(0.).to_radians() );
+            }
+        }
+
+        // The parameters themselves get set every tick in apply
+        p.speed_start = p.speed;
+        p.angular_velocity_start = p.angular_velocity;
+    }
+
+    /// Projectiles go back into the pool
+    fn despawn(commands: &mut Commands, entity: Entity) {
+        commands.despawn_projectile(entity);
+    }
+
+    /// Stretch states interpolate from the values the projectile had at the start of the state
+    fn apply(&self, p: &mut Projectile, _t: &mut Transform, time: f32, progress: f32) {
+        if let Some( speed ) = self.speed {
+            p.speed = p.speed_start + (speed.value(time) + p.speed_offset - p.speed_start) * progress;
+        }
+        if let Some( angular_velocity ) = self.angular_velocity {
+            p.angular_velocity = p.angular_velocity_start + (angular_velocity.value(time) - p.angular_velocity_start) * progress;
+        }
+    }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Deserialize)]
 pub enum ProjectileTargetingType {
     /// Damages enemies
     PLAYER,
@@ -31,13 +75,86 @@ pub enum ProjectileTargetingType {
     ENVIRONMENT
 }
 
+impl ProjectileTargetingType {
+    /// If projectiles with this targeting damage the player, or the enemies
+    pub fn damages(&self, is_player: bool) -> bool {
+        return match self {
+            ProjectileTargetingType::PLAYER => !is_player,
+            ProjectileTargetingType::ENEMY => is_player,
+            ProjectileTargetingType::ENVIRONMENT => true,
+        };
+    }
+}
+
+/// What a projectile does when it runs into a Wall
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+pub enum WallHit {
+    /// Despawns at the wall
+    Destroy,
+    /// Reflects off the wall this many times, and despawns on the next hit
+    Bounce(u32),
+    /// Keeps moving along the wall, without turning
+    Slide,
+}
+
+/// Makes a projectile steer toward the nearest target it can damage
+#[derive(Clone, Copy, Deserialize)]
+pub struct Homing {
+    /// The most the projectile can turn, in degrees per second
+    pub turn_rate: f32,
+    /// Targets further than this are ignored
+    pub radius: f32,
+}
+
+/// How many targets a projectile can pass through before it despawns
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+pub enum Pierce {
+    /// Despawns on the first hit
+    None,
+    /// Passes through this many targets, and despawns on the next one
+    Count(u32),
+    /// Never despawns by hitting things, like beams
+    Infinite,
+}
 
-#[derive(Component)]
+#[derive(Component, Clone)]
 pub struct Projectile {
     pub damage: i32,
+    pub damage_kind: DamageKind,
+    /// Ignores the defense of what it hits
+    pub armor_piercing: bool,
     pub targeting_type: ProjectileTargetingType,
+    /// The entity that fired the projectile, it gets the credit for the damage
+    pub owner: Option<Entity>,
     pub angular_velocity: f32,
     pub speed: f32,
+    /// Added to the speed the states set, so patterns can give every projectile a different speed
+    pub speed_offset: f32,
+
+    /// Values when the current state started, StateDuration::Stretch interpolates from these
+    pub angular_velocity_start: f32,
+    pub speed_start: f32,
+
+    /// Seconds until the projectile expires, None lives until it hits something
+    pub lifetime: Option<f32>,
+    /// The distance the projectile can travel before it expires, like the range of a weapon
+    pub range: Option<f32>,
+    /// Time alive, and distance travelled so far
+    pub age: f32,
+    pub travelled: f32,
+
+    pub pierce: Pierce,
+    /// Entities this projectile already damaged, it hits every target only once
+    pub hit: Vec<Entity>,
+
+    /// Steers toward targets, on top of the angular velocity
+    pub homing: Option<Homing>,
+    /// Status effects applied to what it damages
+    pub on_hit: Vec<StatusEffect>,
+
+    pub wall_hit: WallHit,
+    /// Times the projectile bounced off walls so far
+    pub bounces: u32,
 }
 
 
@@ -45,115 +162,301 @@ impl Default for Projectile {
     fn default() -> Self {
         Projectile {
             damage: 1,
+            damage_kind: DamageKind::Physical,
+            armor_piercing: false,
             angular_velocity: 0.,
             speed: 15.,
+            speed_offset: 0.,
+            angular_velocity_start: 0.,
+            speed_start: 15.,
             targeting_type: ProjectileTargetingType::ENVIRONMENT,
+            owner: None,
+            lifetime: Some(10.),
+            range: None,
+            age: 0.,
+            travelled: 0.,
+            pierce: Pierce::None,
+            hit: Vec::new(),
+            homing: None,
+            on_hit: Vec::new(),
+            wall_hit: WallHit::Destroy,
+            bounces: 0,
         }
     }
 }
 
-/// Stores the handle given by asset_server, so we dont load the image in for every projectile
+impl Projectile {
+    /// If the projectile can still damage the target
+    pub fn can_hit(&self, target: Entity) -> bool {
+        let hits_left = match self.pierce {
+            Pierce::None => self.hit.is_empty(),
+            Pierce::Count( count ) => self.hit.len() <= count as usize,
+            Pierce::Infinite => true,
+        };
+        return hits_left && !self.hit.contains(&target);
+    }
+
+    /// The damage this projectile deals to the target
+    pub fn damage_event(&self, id: Entity, target: Entity) -> DamageEvent {
+        DamageEvent {
+            source: Some(id),
+            attacker: self.owner,
+            target,
+            amount: self.damage,
+            kind: self.damage_kind,
+            armor_piercing: self.armor_piercing,
+            periodic: false,
+        }
+    }
+
+    /// Remembers the target, returns true if the projectile used up its pierce and should despawn
+    pub fn register_hit(&mut self, target: Entity) -> bool {
+        self.hit.push(target);
+        return match self.pierce {
+            Pierce::None => true,
+            Pierce::Count( count ) => self.hit.len() > count as usize,
+            Pierce::Infinite => false,
+        };
+    }
+}
+
+/// Why a projectile expired
+#[derive(Clone, Copy, PartialEq)]
+pub enum ExpireReason {
+    Lifetime,
+    Range,
+    /// It got too far from the camera
+    Culled,
+    /// It ran into a wall
+    Wall,
+}
+
+/// Sent when a projectile despawns without hitting anything
+#[derive(Event)]
+pub struct ProjectileExpired {
+    pub position: Vec2,
+    pub reason: ExpireReason,
+}
+
+/// Projectiles further than this from the camera get despawned, 
+/// so stray bullets don't pile up, even without a lifetime
 #[derive(Resource)]
-pub struct ProjectileAsset {
-    pub handle: Handle<Image>,
+pub struct ProjectileCull {
+    pub distance: f32,
+}
+
+impl Default for ProjectileCull {
+    fn default() -> Self {
+        ProjectileCull { distance: 2000. }
+    }
 }
 
 impl Plugin for ProjectilePlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup);
-        app.add_event::<StatefulEvent<PState>>();
+        app.add_plugins(StatefulPlugin::<PState>::default());
+        app.add_event::<ProjectileExpired>();
+        app.init_resource::<ProjectileCull>();
         app.add_systems(FixedUpdate, 
             (
-                increment_states,
-                update_states.after( increment_states ),
-                update_projectile_position.after( update_states ),
+                spawn_on_finish.after( StatefulSet::Tick ).before( StatefulSet::Finish ),
+                home_projectiles.after( StatefulSet::Apply ),
+                update_projectile_position.after( home_projectiles ),
+                expire_projectiles.after( update_projectile_position ),
             ).in_set(AppSet::Gameplay)
         );
+        app.add_systems(Update, spawn_expire_puffs.in_set(AppSet::Gameplay));
         app.add_systems(PostUpdate, 
             (
                 // update_bullet_collision, // It works, i will keep it for an example
                 player_projectile_detection,
                 enemy_projectile_detection
-            ).in_set(AppSet::Gameplay)
+            ).in_set(AppSet::Gameplay).before(DamageStage::Collect)
         );
     }
 }
 
-fn setup(
+
+/// Spawns the pattern of projectiles with a SpawnOnFinish component, where their sequence ended
+fn spawn_on_finish (
     mut commands: Commands,
-    asset_server: Res<AssetServer>
+    mut finished_event: EventReader<StatefulFinished<PState>>,
+    mut projectiles: Query<(&Transform, &mut SpawnOnFinish)>,
 ) {
-    info!("CALLED!!!");
-    commands.insert_resource(
-        ProjectileAsset {
-            handle: asset_server.load("thing.png"),
-    })
-}
-
-
-fn increment_states (
-    time: Res<Time>,
-    mut state_event: EventWriter<StatefulEvent<PState>>,
-    mut projectiles: Query<(Entity, &mut Stateful<PState>), With<Projectile>>,
-) { 
-    for (id, mut state) in projectiles.iter_mut() {
-        let event = state.update_state(time.delta_seconds());
-        
-
-        match event {
-            Some(mut e) => { 
-               e.entity_id = id; 
-               state_event.send(e);
-             },
-            _ => ()
+    for event in finished_event.read() {
+        if let Ok( (transform, mut spawn) ) = projectiles.get_mut( event.entity_id ) {
+            let dir = Quat::mul_vec3(transform.rotation, Vec3::Y).xy();
+            let sprite = spawn.sprite.clone();
+            spawn.pattern.set_dir( dir );
+            spawn.pattern.spawn( &mut commands, transform, sprite );
         }
     }
 }
 
-fn update_states (
-    mut state_event: EventReader<StatefulEvent<PState>>,
-    mut projectiles: Query<&mut Projectile, With<Stateful<PState>>>,
-) { 
-    for event in state_event.read() {
-        let projectile = projectiles.get_mut( event.entity_id );
-
-        match projectile {
-            Ok( mut p ) => {
-               p.speed = event.state.speed.unwrap_or( p.speed );
-               p.angular_velocity = event.state.angular_velocity.unwrap_or( p.angular_velocity );
-            },
-            Err(err) => warn!("update_projectile_state lost entity from statefulevent, with error {}", err),
+/// Turns homing projectiles toward the nearest target they can damage, that they didn't hit yet
+fn home_projectiles(
+    time: Res<Time>,
+    mut projectiles: Query<(&mut Transform, &Projectile)>,
+    targets: Query<(Entity, &GlobalTransform, Has<Player>), (With<Health>, Without<Projectile>, Without<Dead>)>,
+) {
+    let delta = time.delta_seconds();
+    for (mut t, p) in projectiles.iter_mut() {
+        let homing = match p.homing {
+            Some( homing ) => homing,
+            None => continue,
+        };
+        let position = t.translation.xy();
+
+        let nearest = targets.iter()
+            .filter( |(id, _, is_player)| p.targeting_type.damages(*is_player) && p.can_hit(*id) )
+            .map( |(_, target, _)| target.translation().xy() - position )
+            .filter( |offset| offset.length() <= homing.radius )
+            .min_by( |a, b| a.length_squared().total_cmp( &b.length_squared() ) );
+
+        if let Some( offset ) = nearest {
+            // Projectiles move toward their local up
+            let forward = Quat::mul_vec3(t.rotation, Vec3::Y).xy();
+            let max_turn = homing.turn_rate.to_radians() * delta;
+            let angle = forward.angle_between(offset).clamp(-max_turn, max_turn);
+            if angle.is_finite() {
+                t.rotate_z( angle );
+            }
         }
     }
 }
 
-/// Updates the transform of every projectile, by what their Projectile struct defines
+/// Fraction of the movement kept between projectiles and the walls they hit, so the next cast doesn't start inside the wall
+const WALL_SKIN: f32 = 0.01;
+
+/// Updates the transform of every projectile, by what their Projectile struct defines \
+/// The movement is shape cast against walls, so fast projectiles can't pass through them
 fn update_projectile_position(
     time: Res<Time>,
-    mut projectiles: Query<(Entity,&mut Transform, &Projectile)>,
-    state_projectiles: Query<&Stateful<PState>, With<Projectile>>
+    mut commands: Commands,
+    mut expired_event: EventWriter<ProjectileExpired>,
+    rapier_ctx: Res<RapierContext>,
+    walls: Query<Entity, With<Wall>>,
+    mut projectiles: Query<(Entity, &mut Transform, &mut Projectile, &Collider)>,
 ) {
-    let time = time.delta().as_secs_f32();
-    let fm = 60.; 
-    let one_frame_offset = 1. / ((fm + 1.) / fm); //FIXME: i hate this shit, i want to kms
-    for (e,mut t, p) in projectiles.iter_mut() { 
-        let veloc = p.speed * time * one_frame_offset;
+    let time = time.delta_seconds();
+    let wall_filter = |entity: Entity| walls.contains(entity);
+    let filter = QueryFilter::default()
+        .groups( CollisionGroups::new( Group::ALL, WALL_GROUP ) )
+        .predicate(&wall_filter);
+
+    for (id, mut t, mut p, collider) in projectiles.iter_mut() { 
+        let veloc = p.speed * time;
+        p.age += time;
+        p.travelled += veloc.abs();
+
         let rot = t.rotation;
         // Quat::mul_vec3 multiplies the vector by a rotation, this way our velocity vector points
         // to where our sprite is pointing to
-        t.translation += Quat::mul_vec3(rot, Vec3::new(0., veloc, 0.));
+        let motion = Quat::mul_vec3(rot, Vec3::new(0., veloc, 0.)).xy();
+        let position = t.translation.xy();
+        let angle = rot.to_euler(EulerRot::XYZ).2;
 
-        //TODO: implement stateduration::stretch
-        if state_projectiles.get( e ).is_ok_and( |s| s.state_duration == StateDuration::Instant) {
-            // If the pattern has a duration of zero, we want it to be instant, and not affected
-            // by the delta_time
-            t.rotate_z( ( p.angular_velocity).to_radians());
+        let hit = if motion == Vec2::ZERO {
+            None
         } else {
-            t.rotate_z( ( p.angular_velocity).to_radians() * time * one_frame_offset  );
+            rapier_ctx.cast_shape(position, angle, motion, collider, 1., false, filter)
+        };
+        match hit {
+            Some( (_, toi) ) => {
+                let normal = toi.details.map_or( Vec2::ZERO, |details| details.normal1 ).normalize_or_zero();
+
+                let travel = (toi.toi - WALL_SKIN).max(0.);
+                t.translation += (motion * travel).extend(0.);
+                let remaining = motion * (1. - travel);
+
+                match p.wall_hit {
+                    WallHit::Bounce( max ) if p.bounces < max => {
+                        p.bounces += 1;
+                        let reflected = remaining - 2. * remaining.dot(normal) * normal;
+                        // Face the new direction, speed can be negative so we flip it back
+                        let dir = reflected.normalize_or_zero() * veloc.signum();
+                        if dir != Vec2::ZERO {
+                            t.rotation = Quat::from_rotation_z( Vec2::Y.angle_between(dir) );
+                        }
+                        // The reflected move can run into another wall too, like in corners
+                        let bounce_angle = t.rotation.to_euler(EulerRot::XYZ).2;
+                        let bounce_travel = rapier_ctx.cast_shape(t.translation.xy(), bounce_angle, reflected, collider, 1., false, filter)
+                            .map_or( 1., |(_, toi)| (toi.toi - WALL_SKIN).max(0.) );
+                        t.translation += (reflected * bounce_travel).extend(0.);
+                    }
+                    WallHit::Slide => {
+                        let slide = remaining - remaining.dot(normal) * normal;
+                        let slide_travel = rapier_ctx.cast_shape(t.translation.xy(), angle, slide, collider, 1., false, filter)
+                            .map_or( 1., |(_, toi)| (toi.toi - WALL_SKIN).max(0.) );
+                        t.translation += (slide * slide_travel).extend(0.);
+                    }
+                    _ => {
+                        expired_event.send( ProjectileExpired { position: t.translation.xy(), reason: ExpireReason::Wall } );
+                        commands.despawn_projectile(id);
+                        continue;
+                    }
+                }
+            },
+            None => t.translation += motion.extend(0.),
         }
+
+        t.rotate_z( p.angular_velocity.to_radians() * time );
+    }
+}
+
+
+/// Seconds the puff left by an expired projectile takes to fade out
+pub const EXPIRE_PUFF_DURATION: f32 = 0.2;
+
+/// Leaves a small fading puff where projectiles expired, so it's visible where they hit a wall or ran out
+fn spawn_expire_puffs(
+    mut commands: Commands,
+    mut expired_event: EventReader<ProjectileExpired>,
+) {
+    for event in expired_event.read() {
+        let color = match event.reason {
+            ExpireReason::Wall => Color::WHITE,
+            ExpireReason::Lifetime | ExpireReason::Range => Color::GRAY,
+            // Too far from the camera to be seen anyway
+            ExpireReason::Culled => continue,
+        };
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite { color, custom_size: Some( Vec2::splat(2.) ), ..default() },
+                transform: Transform::from_translation( event.position.extend(0.) ),
+                ..default()
+            },
+            Fading { time: 0., duration: EXPIRE_PUFF_DURATION },
+        ));
     }
 }
 
+/// Despawns the projectiles that ran out of lifetime or range, or got too far from the camera
+fn expire_projectiles(
+    mut commands: Commands,
+    mut expired_event: EventWriter<ProjectileExpired>,
+    cull: Res<ProjectileCull>,
+    camera: Query<&GlobalTransform, With<MainCamera>>,
+    projectiles: Query<(Entity, &Transform, &Projectile)>,
+) {
+    let camera_pos = camera.get_single().ok().map( |t| t.translation().xy() );
+
+    for (id, t, p) in projectiles.iter() {
+        let position = t.translation.xy();
+
+        let reason = if p.lifetime.is_some_and( |lifetime| p.age >= lifetime ) {
+            ExpireReason::Lifetime
+        } else if p.range.is_some_and( |range| p.travelled >= range ) {
+            ExpireReason::Range
+        } else if camera_pos.is_some_and( |cam| cam.distance(position) > cull.distance ) {
+            ExpireReason::Culled
+        } else {
+            continue;
+        };
+
+        expired_event.send( ProjectileExpired { position, reason } );
+        commands.despawn_projectile(id);
+    }
+}
 
 // SOME BIG INFO:
 
@@ -196,30 +499,48 @@ fn update_projectile_position(
 // }
 
 
+/// Damages the target with the projectile, if it can hit it, and applies its status effects \
+/// Despawns the projectile once it used up its pierce
+fn hit_target(
+    commands: &mut Commands,
+    damage_events: &mut EventWriter<DamageEvent>,
+    status_events: &mut EventWriter<ApplyStatus>,
+    id: Entity,
+    projectile: &mut Projectile,
+    target: Entity,
+    target_is_player: bool,
+) {
+    if !projectile.targeting_type.damages(target_is_player) || !projectile.can_hit(target) {
+        return;
+    }
+    damage_events.send( projectile.damage_event(id, target) );
+    for effect in projectile.on_hit.iter() {
+        status_events.send( ApplyStatus { source: Some(id), target, effect: *effect } );
+    }
+    if projectile.register_hit(target) {
+        commands.despawn_projectile(id);
+    }
+}
+
 //TODO: Make a separate enemy and enviroment struct
 fn enemy_projectile_detection(
-    mut entities: Query<(Entity, &Collider, &Transform, &mut Health), (Without<Player>, Without<Projectile>)>,
-    projectiles: Query<(Entity, &Projectile)>,
+    entities: Query<(Entity, &Collider, &Transform), (With<Health>, Without<Player>, Without<Projectile>)>,
+    mut projectiles: Query<(Entity, &mut Projectile)>,
     mut commands: Commands,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut status_events: EventWriter<ApplyStatus>,
     rapier_ctx: Res<RapierContext>,
 ) {
-    for (_, coll, transform, mut health) in entities.iter_mut() {
+    for (target, coll, transform) in entities.iter() {
         rapier_ctx.intersections_with_shape(
             transform.translation.xy(), //pos
             transform.rotation.to_euler(EulerRot::XYZ).2, //rot
             coll, //shape
-            QueryFilter::default(),  // TODO: Figure out how to narrow down the query
+            // Only the projectiles that damage enemies
+            QueryFilter::default().groups( CollisionGroups::new( ENEMY_GROUP, PLAYER_PROJECTILE_GROUP ) ),
             |entity| {
-                let p_e = projectiles.get(entity);
-                if p_e.is_ok() {
-                    let (id, projectile) = p_e.unwrap();
-                    match projectile.targeting_type {
-                        ProjectileTargetingType::ENVIRONMENT | ProjectileTargetingType::PLAYER => {
-                            health.current -= projectile.damage;
-                            commands.entity(id).despawn();
-                        }
-                        _ => ()
-                    }
+                if let Ok( (id, mut projectile) ) = projectiles.get_mut(entity) {
+                    hit_target( &mut commands, &mut damage_events, &mut status_events, id, &mut projectile, target, false );
                 }
                 true
             }
@@ -228,29 +549,24 @@ fn enemy_projectile_detection(
 }
 
 fn player_projectile_detection(
-    mut player: Query<(Entity, &Collider, &Transform, &mut Health), With<Player>>, 
-    projectiles: Query<(Entity, &Projectile)>,
+    player: Query<(Entity, &Collider, &Transform), (With<Player>, With<Health>)>, 
+    mut projectiles: Query<(Entity, &mut Projectile)>,
     mut commands: Commands,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut status_events: EventWriter<ApplyStatus>,
     rapier_ctx: Res<RapierContext>,
 ) {
-    if let Ok( (_, coll, transform, mut health) ) = player.get_single_mut() {
+    if let Ok( (target, coll, transform) ) = player.get_single() {
 
         rapier_ctx.intersections_with_shape(
             transform.translation.xy(), //pos
             transform.rotation.to_euler(EulerRot::XYZ).2, //rot
             coll, //shape
-            QueryFilter::default(), 
+            // Only the projectiles that damage the player
+            QueryFilter::default().groups( CollisionGroups::new( PLAYER_GROUP, ENEMY_PROJECTILE_GROUP ) ),
             |entity| {
-                let p_e = projectiles.get(entity);
-                if p_e.is_ok() {
-                    let (id, projectile) = p_e.unwrap();
-                    match projectile.targeting_type {
-                        ProjectileTargetingType::ENVIRONMENT | ProjectileTargetingType::ENEMY => {
-                            health.current -= projectile.damage;
-                            commands.entity(id).despawn();
-                        }
-                        _ => ()
-                    }
+                if let Ok( (id, mut projectile) ) = projectiles.get_mut(entity) {
+                    hit_target( &mut commands, &mut damage_events, &mut status_events, id, &mut projectile, target, true );
                 }
                 true
             }
@@ -258,4 +574,105 @@ fn player_projectile_detection(
 
     }
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pierce_none_hits_once() {
+        let mut projectile = Projectile::default();
+        let (a, b) = (Entity::from_raw(1), Entity::from_raw(2));
+
+        assert!(projectile.can_hit(a));
+        assert!(projectile.register_hit(a));
+        assert!(!projectile.can_hit(a));
+        assert!(!projectile.can_hit(b));
+    }
+
+    #[test]
+    fn pierce_count_passes_through_count_targets() {
+        let mut projectile = Projectile { pierce: Pierce::Count(2), ..default() };
+        let targets: Vec<Entity> = (1..=4).map( Entity::from_raw ).collect();
+
+        assert!(!projectile.register_hit(targets[0]));
+        assert!(!projectile.register_hit(targets[1]));
+        assert!(projectile.can_hit(targets[2]));
+        assert!(projectile.register_hit(targets[2]));
+        assert!(!projectile.can_hit(targets[3]));
+    }
+
+    #[test]
+    fn pierce_infinite_hits_every_target_once() {
+        let mut projectile = Projectile { pierce: Pierce::Infinite, ..default() };
+
+        for i in 1..=100 {
+            let target = Entity::from_raw(i);
+            assert!(projectile.can_hit(target));
+            assert!(!projectile.register_hit(target));
+            assert!(!projectile.can_hit(target));
+        }
+    }
+
+    #[test]
+    fn bounces_off_rotated_walls() {
+        use std::time::Duration;
+        use bevy::time::TimeUpdateStrategy;
+        use bevy_rapier2d::prelude::{NoUserData, RapierPhysicsPlugin};
+        use crate::{pool::ProjectilePoolPlugin, wall::WallBundle};
+
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            TransformPlugin,
+            HierarchyPlugin,
+            AssetPlugin::default(),
+            RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.),
+            ProjectilePoolPlugin,
+        ));
+        app.init_asset::<Image>();
+        app.add_event::<ProjectileExpired>();
+        app.insert_resource( TimeUpdateStrategy::ManualDuration( Duration::from_secs_f32(1. / 60.) ) );
+        app.add_systems(Update, update_projectile_position);
+
+        // The top of the wall faces up and to the left
+        let wall = Transform::from_rotation( Quat::from_rotation_z( 45_f32.to_radians() ) );
+        app.world.spawn( WallBundle::new( wall, Vec2::new(20., 2.) ) );
+        // Lets rapier add the wall, the first frame has no delta time either
+        app.update();
+        app.update();
+
+        // Falls straight down onto the wall
+        let projectile = app.world.spawn((
+            Transform::from_xyz(0., 10., 0.).with_rotation( Quat::from_rotation_z( std::f32::consts::PI ) ),
+            Projectile { speed: 600., wall_hit: WallHit::Bounce(1), ..default() },
+            Collider::cuboid(1., 1.),
+        )).id();
+        app.update();
+
+        let projectile = app.world.entity(projectile);
+        assert_eq!(projectile.get::<Projectile>().unwrap().bounces, 1);
+        let forward = Quat::mul_vec3( projectile.get::<Transform>().unwrap().rotation, Vec3::Y ).xy();
+        assert!(forward.abs_diff_eq( Vec2::NEG_X, 1e-3 ), "bounced toward {}", forward);
+    }
+
+    #[test]
+    fn states_keep_the_speed_offset() {
+        let mut projectile = Projectile { speed: 17., speed_start: 17., speed_offset: 5., ..default() };
+        let mut transform = Transform::default();
+
+        let fixed = PState { speed: Some(StateParam::Constant(12.)), angular_velocity: None, duration: StateDuration::Fixed(1.) };
+        fixed.enter(&mut projectile, &mut transform);
+        fixed.apply(&mut projectile, &mut transform, 0., 1.);
+        assert_eq!(projectile.speed, 17.);
+
+        let ramp = PState { speed: Some(StateParam::Ramp { from: 10., rate: 10. }), angular_velocity: None, duration: StateDuration::Fixed(1.) };
+        ramp.apply(&mut projectile, &mut transform, 0.5, 1.);
+        assert_eq!(projectile.speed, 20.);
+
+        let instant = PState { speed: Some(StateParam::Constant(1.)), angular_velocity: None, duration: StateDuration::Instant };
+        instant.enter(&mut projectile, &mut transform);
+        assert_eq!(projectile.speed, 6.);
+    }
+}
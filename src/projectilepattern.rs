@@ -1,25 +1,144 @@
 use bevy::{math::f32, prelude::*};
 
-use crate::{projectile::{PState, Projectile, ProjectileTargetingType}, stateful::{StateDuration, StateRepeat, Stateful}};
-use bevy_rapier2d::prelude::*;
+use crate::{bulletpattern::BulletPattern, damage::DamageRange, pool::ProjectileCommands, projectile::{PState, Projectile}, stateful::{StateDuration, StateParam::{self, Constant}, StateRepeat, StatefulFinish}, weapon::WeaponDefinition};
+
+use crate::states::AppSet;
+
+pub struct PatternPlugin;
+
+impl Plugin for PatternPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(FixedUpdate, play_patterns.in_set(AppSet::Gameplay));
+    }
+}
 
 /// Used for building custom bullet patterns, 
 /// its better if we centralize the syntax a bit
 pub trait IPPattern {
+    /// Spawns the whole pattern right now, ignoring any delays in it
     fn spawn( 
         &mut self,
-        commands: Commands, 
+        commands: &mut Commands, 
         center: &Transform, 
         sprite: Handle<Image>);
+
+    /// Points the pattern towards dir, the next volley will use it
+    fn set_dir(&mut self, dir: Vec2);
+
+    /// The direction the pattern points towards
+    fn dir(&self) -> Vec2;
+
+    /// Calls f with the template of every projectile the pattern spawns, to change them between volleys
+    fn for_each_template(&mut self, f: &mut dyn FnMut(&mut ProjectileTemplate));
+
+    /// The time it takes for the pattern to spawn everything, single volleys take none
+    fn duration(&self) -> f32 {
+        0.
+    }
+
+    /// Spawns the volleys that are due between from (inclusive) and to, 
+    /// in seconds since the pattern started \
+    /// Single volleys are due at 0, combinators offset the times of their patterns
+    fn spawn_between(
+        &mut self,
+        commands: &mut Commands, 
+        center: &Transform, 
+        sprite: Handle<Image>,
+        from: f32,
+        to: f32
+    ) {
+        if from <= 0. && 0. < to {
+            self.spawn( commands, center, sprite );
+        }
+    }
+}
+
+pub type BoxedPattern = Box<dyn IPPattern + Send + Sync>;
+
+/// Returns the center transform, rotated to point towards dir
+fn aim(center: &Transform, dir: Vec2) -> Transform {
+    if dir == Vec2::ZERO {
+        return center.with_rotation( Quat::IDENTITY );
+    }
+    // Create quat pointing to given direction
+    let dir_quat = Quat::from_rotation_arc(Vec3::Y, dir.normalize().extend(0.));
+    return center.with_rotation( dir_quat );
+}
+
+/// Spawns the pattern where the projectile is, facing where it flies, when its Stateful sequence finishes \
+/// Use it with StatefulFinish::Despawn to make projectiles split or explode
+#[derive(Component)]
+pub struct SpawnOnFinish {
+    pub pattern: BoxedPattern,
+    pub sprite: Handle<Image>,
+}
+
+/// Describes every projectile a pattern spawns
+#[derive(Clone)]
+pub struct ProjectileTemplate {
+    /// Damage, targeting, and the starting speed of the projectile
+    pub base: Projectile,
+    /// Rolled for every projectile, and replaces the damage of base
+    pub damage_range: Option<DamageRange>,
+    /// Half extents of the cuboid collider
+    pub collider: Vec2,
+    /// The state sequence of the projectile, no Stateful gets added if it's empty
+    pub states: Vec<PState>,
+    pub state_repeat: StateRepeat,
+    /// What the projectile does when its state sequence finishes, only happens with StateRepeat::None
+    pub on_finish: StatefulFinish<PState>,
+    /// The pattern file spawned where the projectile is when its state sequence finishes, 
+    /// its projectiles get the owner and targeting of this one
+    pub spawn_on_finish: Option<Handle<BulletPattern>>,
+    /// The pattern file this template came from, the projectiles get updated when it's reloaded
+    pub source: Option<Handle<BulletPattern>>,
+    /// The weapon that fired the projectile, its overrides are applied again when the pattern file is reloaded
+    pub weapon: Option<Handle<WeaponDefinition>>,
+    /// Replaces the sprite the pattern is spawned with, so patterns combined from several files keep their own sprites
+    pub sprite: Option<Handle<Image>>,
+}
+
+impl Default for ProjectileTemplate {
+    fn default() -> Self {
+        ProjectileTemplate {
+            base: Projectile::default(),
+            damage_range: None,
+            collider: Vec2::new(1., 4.),
+            states: vec![
+                PState { speed: Some(Constant(12.)),  angular_velocity: None,                   duration: StateDuration::Fixed(2.)},
+                PState { speed: None,                 angular_velocity: Some(Constant(-120.)),  duration: StateDuration::Fixed(1.)},
+                PState { speed: None,                 angular_velocity: Some(Constant(0.)),     duration: StateDuration::Fixed(0.1)},
+                PState { speed: None,                 angular_velocity: Some(Constant(120.)),   duration: StateDuration::Fixed(1.)},
+                PState { speed: None,                 angular_velocity: Some(Constant(0.)),     duration: StateDuration::Fixed(0.1)},
+            ],
+            state_repeat: StateRepeat::FromIndex(1),
+            on_finish: StatefulFinish::None,
+            spawn_on_finish: None,
+            source: None,
+            weapon: None,
+            sprite: None,
+        }
+    }
+}
+
+impl ProjectileTemplate {
+    /// Spawns a single projectile with the given transform, reusing one from the ProjectilePool if it can
+    pub fn spawn(
+        &self,
+        commands: &mut Commands,
+        transform: Transform,
+        sprite: Handle<Image>
+    ) {
+        commands.spawn_projectile( self, transform, self.sprite.clone().unwrap_or(sprite) );
+    }
 }
 
 pub struct CirclePattern{
-    // pub stateful: PPattern,
     pub amount: i32,
     pub dir: Vec2,
     pub max_deg: f32,
 
-    pub targeting: ProjectileTargetingType,
+    pub projectile: ProjectileTemplate,
 }
 
 impl Default for CirclePattern {
@@ -28,7 +147,7 @@ impl Default for CirclePattern {
             amount: 0,
             max_deg: 360.,
             dir: Vec2::new(0.,1.),
-            targeting: ProjectileTargetingType::ENVIRONMENT
+            projectile: ProjectileTemplate::default(),
         }
     }
 }
@@ -36,7 +155,7 @@ impl Default for CirclePattern {
 impl IPPattern for CirclePattern {
     fn spawn(
         &mut self,
-        mut commands: Commands,
+        commands: &mut Commands,
         center: &Transform, 
         sprite: Handle<Image>
     ) {
@@ -47,11 +166,8 @@ impl IPPattern for CirclePattern {
         let deg = self.max_deg / self.amount as f32;
         let deg_offset = self.max_deg / 2.;
 
-        // Create quat pointing to given direction
-        let dir_quat = Quat::from_rotation_arc(Vec3::Y, self.dir.extend(0.));
-
-        // Create transform with quat
-        let mut base_transform = center.with_rotation( dir_quat );
+        // Create transform pointing to the given direction
+        let mut base_transform = aim( center, self.dir );
 
         // Rotate transform to the beginning of the pattern
         base_transform.rotate_z( (deg_offset).to_radians() ); 
@@ -64,44 +180,734 @@ impl IPPattern for CirclePattern {
         base_transform.rotate_z( -(deg / 2.).to_radians() );
 
         for _ in 1..=self.amount {
-            let _ = commands.spawn(
-                (
-                    SpriteBundle {
-                        transform: base_transform, 
-                        texture: sprite.clone(),
-                        ..default()
-                    },
-                    Projectile {
-                        targeting_type: self.targeting,
-                        ..default()
-                    },
-
-                    Stateful::from_states( 
-                        vec![
-                            PState { speed: Some(12.),  angular_velocity: None,          duration: StateDuration::Fixed(2.)},
-                            PState { speed: None,       angular_velocity: Some(-120.),   duration: StateDuration::Fixed(1.)},
-                            PState { speed: None,       angular_velocity: Some(0.),      duration: StateDuration::Fixed(0.1)},
-                            PState { speed: None,       angular_velocity: Some(120.),    duration: StateDuration::Fixed(1.)},
-                            PState { speed: None,       angular_velocity: Some(0.),      duration: StateDuration::Fixed(0.1)},
-                        ], StateRepeat::FromIndex(1) ),
-                    Collider::cuboid(1., 4.),
-                )
-            ).id();
+            self.projectile.spawn( commands, base_transform, sprite.clone() );
 
             // Rotate after spawning first bullet
             base_transform.rotate_z( -deg.to_radians() );
+        }
+    }
+
+    fn set_dir(&mut self, dir: Vec2) {
+        self.dir = dir;
+    }
+
+    fn dir(&self) -> Vec2 {
+        self.dir
+    }
+
+    fn for_each_template(&mut self, f: &mut dyn FnMut(&mut ProjectileTemplate)) {
+        f(&mut self.projectile);
+    }
+}
+
+/// Spawns `arms` projectiles evenly around the center, 
+/// and rotates by step_deg after every volley
+pub struct SpiralPattern {
+    pub arms: i32,
+    pub dir: Vec2,
+    /// Degrees the spiral rotates between volleys
+    pub step_deg: f32,
+    /// The current rotation of the spiral, in degrees
+    pub angle: f32,
+
+    pub projectile: ProjectileTemplate,
+}
+
+impl Default for SpiralPattern {
+    fn default() -> Self {
+        SpiralPattern {
+            arms: 1,
+            dir: Vec2::new(0.,1.),
+            step_deg: 10.,
+            angle: 0.,
+            projectile: ProjectileTemplate::default(),
+        }
+    }
+}
+
+impl IPPattern for SpiralPattern {
+    fn spawn(
+        &mut self,
+        commands: &mut Commands,
+        center: &Transform, 
+        sprite: Handle<Image>
+    ) {
+        let deg = 360. / self.arms as f32;
+        let mut base_transform = aim( center, self.dir );
+        base_transform.rotate_z( -self.angle.to_radians() );
+
+        for _ in 1..=self.arms {
+            self.projectile.spawn( commands, base_transform, sprite.clone() );
+            base_transform.rotate_z( -deg.to_radians() );
+        }
+
+        self.angle = (self.angle + self.step_deg) % 360.;
+    }
+
+    fn set_dir(&mut self, dir: Vec2) {
+        self.dir = dir;
+    }
+
+    fn dir(&self) -> Vec2 {
+        self.dir
+    }
+
+    fn for_each_template(&mut self, f: &mut dyn FnMut(&mut ProjectileTemplate)) {
+        f(&mut self.projectile);
+    }
+}
+
+/// Spreads the projectiles over max_deg, with the outer ones on the edges, 
+/// every projectile gets a random angle and speed offset
+pub struct ShotgunPattern {
+    pub amount: i32,
+    pub dir: Vec2,
+    pub max_deg: f32,
+    /// Max random angle offset of a projectile in degrees, in both directions
+    pub jitter_deg: f32,
+    /// Max random speed offset of a projectile, in both directions
+    pub jitter_speed: f32,
+    pub rng: fastrand::Rng,
+
+    pub projectile: ProjectileTemplate,
+}
+
+impl Default for ShotgunPattern {
+    fn default() -> Self {
+        ShotgunPattern {
+            amount: 5,
+            dir: Vec2::new(0.,1.),
+            max_deg: 45.,
+            jitter_deg: 5.,
+            jitter_speed: 0.,
+            rng: fastrand::Rng::new(),
+            projectile: ProjectileTemplate::default(),
+        }
+    }
+}
+
+impl IPPattern for ShotgunPattern {
+    fn spawn(
+        &mut self,
+        commands: &mut Commands,
+        center: &Transform, 
+        sprite: Handle<Image>
+    ) {
+        // With a single projectile there is nothing to spread
+        let deg = if self.amount > 1 { self.max_deg / (self.amount - 1) as f32 } else { 0. };
+        let mut base_transform = aim( center, self.dir );
+        if self.amount > 1 {
+            base_transform.rotate_z( (self.max_deg / 2.).to_radians() );
+        }
+
+        for _ in 1..=self.amount {
+            let jitter = (self.rng.f32() * 2. - 1.) * self.jitter_deg;
+            let mut transform = base_transform;
+            transform.rotate_z( jitter.to_radians() );
+
+            let mut projectile = self.projectile.clone();
+            let jitter_speed = (self.rng.f32() * 2. - 1.) * self.jitter_speed;
+            projectile.base.speed += jitter_speed;
+            projectile.base.speed_start = projectile.base.speed;
+            projectile.base.speed_offset += jitter_speed;
+            projectile.spawn( commands, transform, sprite.clone() );
+
+            base_transform.rotate_z( -deg.to_radians() );
+        }
+    }
+
+    fn set_dir(&mut self, dir: Vec2) {
+        self.dir = dir;
+    }
+
+    fn dir(&self) -> Vec2 {
+        self.dir
+    }
 
-            // self.base.bullets.push(id);
+    fn for_each_template(&mut self, f: &mut dyn FnMut(&mut ProjectileTemplate)) {
+        f(&mut self.projectile);
+    }
+}
+
+/// Spawns rings of projectiles at the same time, every ring is faster than the previous one, 
+/// so they spread out into concentric rings \
+/// The slots in gaps are left empty in every ring, so the player has somewhere to go
+pub struct RingPattern {
+    /// Projectiles in a full ring
+    pub amount: i32,
+    pub rings: i32,
+    pub dir: Vec2,
+    /// Speed added to every ring after the first
+    pub ring_speed_step: f32,
+    /// Indexes of the slots that are left empty, 0 is the one facing dir
+    pub gaps: Vec<i32>,
+    /// Every ring moves its gaps by this many slots
+    pub gap_shift: i32,
+
+    pub projectile: ProjectileTemplate,
+}
+
+impl Default for RingPattern {
+    fn default() -> Self {
+        RingPattern {
+            amount: 16,
+            rings: 3,
+            dir: Vec2::new(0.,1.),
+            ring_speed_step: 5.,
+            gaps: vec![0],
+            gap_shift: 0,
+            projectile: ProjectileTemplate::default(),
         }
+    }
+}
 
-        // One possible way of updating the bullets is to reconstruct the base
-        // Every spawn iteration, and just spawn a PPattern clone as a component
-        // This way every PPatern will have unique entity ID-s
+impl IPPattern for RingPattern {
+    fn spawn(
+        &mut self,
+        commands: &mut Commands,
+        center: &Transform, 
+        sprite: Handle<Image>
+    ) {
+        if self.amount <= 0 {
+            return;
+        }
+        let deg = 360. / self.amount as f32;
+
+        for ring in 0..self.rings {
+            let mut projectile = self.projectile.clone();
+            let ring_speed = self.ring_speed_step * ring as f32;
+            projectile.base.speed += ring_speed;
+            projectile.base.speed_start = projectile.base.speed;
+            projectile.base.speed_offset += ring_speed;
+
+            let mut transform = aim( center, self.dir );
+            for slot in 0..self.amount {
+                let is_gap = self.gaps.iter()
+                    .any( |gap| (gap + self.gap_shift * ring).rem_euclid(self.amount) == slot );
+
+                if !is_gap {
+                    projectile.spawn( commands, transform, sprite.clone() );
+                }
+                transform.rotate_z( -deg.to_radians() );
+            }
+        }
+    }
 
-        //TODO: idk xd
-        // if self.base_id.is_none() {
-        //     let id = commands.spawn(self.base).id();
-        //     self.base_id = Some(id);
-        // }
+    fn set_dir(&mut self, dir: Vec2) {
+        self.dir = dir;
     }
-}
\ No newline at end of file
+
+    fn dir(&self) -> Vec2 {
+        self.dir
+    }
+
+    fn for_each_template(&mut self, f: &mut dyn FnMut(&mut ProjectileTemplate)) {
+        f(&mut self.projectile);
+    }
+}
+
+/// Streams of projectiles that wave left and right around their direction \
+/// Replaces the states of the projectile with a cosine angular velocity
+pub struct WavePattern {
+    pub streams: i32,
+    pub dir: Vec2,
+    /// Spread of the streams in degrees
+    pub max_deg: f32,
+    /// Max angular velocity of the wave, in degrees per second
+    pub amplitude: f32,
+    /// Waves per second
+    pub frequency: f32,
+    /// Every other stream waves the other way, so they cross each other
+    pub mirror: bool,
+
+    pub projectile: ProjectileTemplate,
+}
+
+impl Default for WavePattern {
+    fn default() -> Self {
+        WavePattern {
+            streams: 2,
+            dir: Vec2::new(0.,1.),
+            max_deg: 0.,
+            amplitude: 180.,
+            frequency: 1.,
+            mirror: true,
+            projectile: ProjectileTemplate::default(),
+        }
+    }
+}
+
+impl IPPattern for WavePattern {
+    fn spawn(
+        &mut self,
+        commands: &mut Commands,
+        center: &Transform, 
+        sprite: Handle<Image>
+    ) {
+        let deg = if self.streams > 1 { self.max_deg / (self.streams - 1) as f32 } else { 0. };
+        let mut base_transform = aim( center, self.dir );
+        if self.streams > 1 {
+            base_transform.rotate_z( (self.max_deg / 2.).to_radians() );
+        }
+        // A single state that lasts a whole wave, and repeats
+        let period = if self.frequency > 0. { 1. / self.frequency } else { f32::MAX };
+
+        for stream in 0..self.streams {
+            // The integral of cos is sin, so the heading swings around dir, instead of drifting to one side
+            let phase = if self.mirror && stream % 2 == 1 { std::f32::consts::PI } else { 0. };
+
+            let mut projectile = self.projectile.clone();
+            projectile.states = vec![
+                PState {
+                    speed: None,
+                    angular_velocity: Some(StateParam::Cosine { base: 0., amplitude: self.amplitude, frequency: self.frequency, phase }),
+                    duration: StateDuration::Fixed(period)
+                },
+            ];
+            projectile.state_repeat = StateRepeat::All;
+            projectile.spawn( commands, base_transform, sprite.clone() );
+
+            base_transform.rotate_z( -deg.to_radians() );
+        }
+    }
+
+    fn set_dir(&mut self, dir: Vec2) {
+        self.dir = dir;
+    }
+
+    fn dir(&self) -> Vec2 {
+        self.dir
+    }
+
+    fn for_each_template(&mut self, f: &mut dyn FnMut(&mut ProjectileTemplate)) {
+        f(&mut self.projectile);
+    }
+}
+
+/// Sprays projectiles in random directions inside max_deg, with random speeds \
+/// Two sprays with the same seed are the same
+pub struct RandomSprayPattern {
+    pub amount: i32,
+    pub dir: Vec2,
+    pub max_deg: f32,
+    /// Max random speed offset of a projectile, in both directions
+    pub jitter_speed: f32,
+    pub rng: fastrand::Rng,
+
+    pub projectile: ProjectileTemplate,
+}
+
+impl Default for RandomSprayPattern {
+    fn default() -> Self {
+        RandomSprayPattern {
+            amount: 8,
+            dir: Vec2::new(0.,1.),
+            max_deg: 90.,
+            jitter_speed: 3.,
+            rng: fastrand::Rng::new(),
+            projectile: ProjectileTemplate::default(),
+        }
+    }
+}
+
+impl IPPattern for RandomSprayPattern {
+    fn spawn(
+        &mut self,
+        commands: &mut Commands,
+        center: &Transform, 
+        sprite: Handle<Image>
+    ) {
+        let base_transform = aim( center, self.dir );
+
+        for _ in 1..=self.amount {
+            let angle = (self.rng.f32() - 0.5) * self.max_deg;
+            let mut transform = base_transform;
+            transform.rotate_z( angle.to_radians() );
+
+            let mut projectile = self.projectile.clone();
+            let jitter_speed = (self.rng.f32() * 2. - 1.) * self.jitter_speed;
+            projectile.base.speed += jitter_speed;
+            projectile.base.speed_start = projectile.base.speed;
+            projectile.base.speed_offset += jitter_speed;
+            projectile.spawn( commands, transform, sprite.clone() );
+        }
+    }
+
+    fn set_dir(&mut self, dir: Vec2) {
+        self.dir = dir;
+    }
+
+    fn dir(&self) -> Vec2 {
+        self.dir
+    }
+
+    fn for_each_template(&mut self, f: &mut dyn FnMut(&mut ProjectileTemplate)) {
+        f(&mut self.projectile);
+    }
+}
+
+
+/// Plays a pattern over time, from the position of its entity \
+/// Removes itself when the pattern is done
+#[derive(Component)]
+pub struct PatternPlayer {
+    pub pattern: BoxedPattern,
+    pub sprite: Handle<Image>,
+    /// Time since the pattern started
+    pub elapsed: f32,
+    /// Despawns the whole entity when the pattern is done, for players that were spawned on their own
+    pub despawn_when_done: bool,
+}
+
+impl PatternPlayer {
+    pub fn new(pattern: BoxedPattern, sprite: Handle<Image>) -> Self {
+        PatternPlayer {
+            pattern,
+            sprite,
+            elapsed: 0.,
+            despawn_when_done: false,
+        }
+    }
+
+    /// Spawns an entity that plays the pattern at transform, and despawns when it's done
+    pub fn spawn_at(commands: &mut Commands, transform: Transform, pattern: BoxedPattern, sprite: Handle<Image>) -> Entity {
+        let player = PatternPlayer {
+            despawn_when_done: true,
+            ..PatternPlayer::new( pattern, sprite )
+        };
+        return commands.spawn( (TransformBundle::from_transform( transform ), player) ).id();
+    }
+}
+
+fn play_patterns(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut players: Query<(Entity, &GlobalTransform, &mut PatternPlayer)>,
+) {
+    for (id, transform, mut player) in players.iter_mut() {
+        let from = player.elapsed;
+        let to = from + time.delta_seconds();
+        let center = transform.compute_transform();
+        let sprite = player.sprite.clone();
+
+        player.pattern.spawn_between( &mut commands, &center, sprite, from, to );
+        player.elapsed = to;
+
+        if to > player.pattern.duration() {
+            if player.despawn_when_done {
+                commands.entity(id).despawn_recursive();
+            } else {
+                commands.entity(id).remove::<PatternPlayer>();
+            }
+        }
+    }
+}
+
+/// Checks if a pattern of the given duration, starting at 0, has anything to spawn between from and to
+pub fn in_window(from: f32, to: f32, duration: f32) -> bool {
+    0. < to && from <= duration
+}
+
+/// Rotates dir counter-clockwise by deg degrees
+pub fn rotate_dir(dir: Vec2, deg: f32) -> Vec2 {
+    Vec2::from_angle( deg.to_radians() ).rotate( dir )
+}
+
+/// Plays the patterns one after the other, 
+/// every pattern waits for the previous one to finish, and then for its own delay
+#[derive(Default)]
+pub struct SequencePattern {
+    /// The delay before the pattern in seconds, and the pattern
+    pub patterns: Vec<(f32, BoxedPattern)>,
+}
+
+impl SequencePattern {
+    pub fn new() -> Self {
+        SequencePattern { patterns: Vec::new() }
+    }
+
+    /// Adds a pattern after the previous ones, waiting delay seconds before it
+    pub fn then(mut self, delay: f32, pattern: BoxedPattern) -> Self {
+        self.patterns.push( (delay, pattern) );
+        self
+    }
+}
+
+impl IPPattern for SequencePattern {
+    fn spawn(
+        &mut self,
+        commands: &mut Commands,
+        center: &Transform, 
+        sprite: Handle<Image>
+    ) {
+        self.spawn_between( commands, center, sprite, 0., f32::INFINITY );
+    }
+
+    fn set_dir(&mut self, dir: Vec2) {
+        for (_, pattern) in self.patterns.iter_mut() {
+            pattern.set_dir(dir);
+        }
+    }
+
+    fn dir(&self) -> Vec2 {
+        self.patterns.first().map_or( Vec2::Y, |(_, pattern)| pattern.dir() )
+    }
+
+    fn for_each_template(&mut self, f: &mut dyn FnMut(&mut ProjectileTemplate)) {
+        for (_, pattern) in self.patterns.iter_mut() {
+            pattern.for_each_template(f);
+        }
+    }
+
+    fn duration(&self) -> f32 {
+        self.patterns.iter().map( |(delay, pattern)| delay + pattern.duration() ).sum()
+    }
+
+    fn spawn_between(
+        &mut self,
+        commands: &mut Commands, 
+        center: &Transform, 
+        sprite: Handle<Image>,
+        from: f32,
+        to: f32
+    ) {
+        let mut offset = 0.;
+        for (delay, pattern) in self.patterns.iter_mut() {
+            offset += *delay;
+            let duration = pattern.duration();
+            if in_window( from - offset, to - offset, duration ) {
+                pattern.spawn_between( commands, center, sprite.clone(), from - offset, to - offset );
+            }
+            offset += duration;
+        }
+    }
+}
+
+/// Plays every pattern at the same time
+pub struct ParallelPattern {
+    pub patterns: Vec<BoxedPattern>,
+}
+
+impl IPPattern for ParallelPattern {
+    fn spawn(
+        &mut self,
+        commands: &mut Commands,
+        center: &Transform, 
+        sprite: Handle<Image>
+    ) {
+        self.spawn_between( commands, center, sprite, 0., f32::INFINITY );
+    }
+
+    fn set_dir(&mut self, dir: Vec2) {
+        for pattern in self.patterns.iter_mut() {
+            pattern.set_dir(dir);
+        }
+    }
+
+    fn dir(&self) -> Vec2 {
+        self.patterns.first().map_or( Vec2::Y, |pattern| pattern.dir() )
+    }
+
+    fn for_each_template(&mut self, f: &mut dyn FnMut(&mut ProjectileTemplate)) {
+        for pattern in self.patterns.iter_mut() {
+            pattern.for_each_template(f);
+        }
+    }
+
+    fn duration(&self) -> f32 {
+        self.patterns.iter().map( |pattern| pattern.duration() ).fold(0., f32::max)
+    }
+
+    fn spawn_between(
+        &mut self,
+        commands: &mut Commands, 
+        center: &Transform, 
+        sprite: Handle<Image>,
+        from: f32,
+        to: f32
+    ) {
+        for pattern in self.patterns.iter_mut() {
+            if in_window( from, to, pattern.duration() ) {
+                pattern.spawn_between( commands, center, sprite.clone(), from, to );
+            }
+        }
+    }
+}
+
+/// Plays the pattern count times, every interval seconds, 
+/// rotating it by rotate_deg (counter-clockwise) every time
+pub struct RepeatPattern {
+    pub pattern: BoxedPattern,
+    pub count: i32,
+    pub interval: f32,
+    pub rotate_deg: f32,
+    pub dir: Vec2,
+}
+
+impl RepeatPattern {
+    /// Keeps the direction of the pattern
+    pub fn new(pattern: BoxedPattern, count: i32, interval: f32) -> Self {
+        RepeatPattern {
+            dir: pattern.dir(),
+            pattern,
+            count,
+            interval,
+            rotate_deg: 0.,
+        }
+    }
+}
+
+impl IPPattern for RepeatPattern {
+    fn spawn(
+        &mut self,
+        commands: &mut Commands,
+        center: &Transform, 
+        sprite: Handle<Image>
+    ) {
+        self.spawn_between( commands, center, sprite, 0., f32::INFINITY );
+    }
+
+    fn set_dir(&mut self, dir: Vec2) {
+        self.dir = dir;
+    }
+
+    fn dir(&self) -> Vec2 {
+        self.dir
+    }
+
+    fn for_each_template(&mut self, f: &mut dyn FnMut(&mut ProjectileTemplate)) {
+        self.pattern.for_each_template(f);
+    }
+
+    fn duration(&self) -> f32 {
+        if self.count <= 0 {
+            return 0.;
+        }
+        self.interval * (self.count - 1) as f32 + self.pattern.duration()
+    }
+
+    fn spawn_between(
+        &mut self,
+        commands: &mut Commands, 
+        center: &Transform, 
+        sprite: Handle<Image>,
+        from: f32,
+        to: f32
+    ) {
+        let duration = self.pattern.duration();
+        for i in 0..self.count {
+            let offset = self.interval * i as f32;
+            if in_window( from - offset, to - offset, duration ) {
+                self.pattern.set_dir( rotate_dir( self.dir, self.rotate_deg * i as f32 ) );
+                self.pattern.spawn_between( commands, center, sprite.clone(), from - offset, to - offset );
+            }
+        }
+    }
+}
+
+/// Rotates the direction of the pattern over time, 
+/// every volley is rotated by how much time passed since the start
+pub struct RotatingPattern {
+    pub pattern: BoxedPattern,
+    /// Counter-clockwise rotation in degrees per second
+    pub deg_per_second: f32,
+    pub dir: Vec2,
+}
+
+impl RotatingPattern {
+    /// Keeps the direction of the pattern
+    pub fn new(pattern: BoxedPattern, deg_per_second: f32) -> Self {
+        RotatingPattern {
+            dir: pattern.dir(),
+            pattern,
+            deg_per_second,
+        }
+    }
+}
+
+impl IPPattern for RotatingPattern {
+    fn spawn(
+        &mut self,
+        commands: &mut Commands,
+        center: &Transform, 
+        sprite: Handle<Image>
+    ) {
+        self.spawn_between( commands, center, sprite, 0., f32::INFINITY );
+    }
+
+    fn set_dir(&mut self, dir: Vec2) {
+        self.dir = dir;
+    }
+
+    fn dir(&self) -> Vec2 {
+        self.dir
+    }
+
+    fn for_each_template(&mut self, f: &mut dyn FnMut(&mut ProjectileTemplate)) {
+        self.pattern.for_each_template(f);
+    }
+
+    fn duration(&self) -> f32 {
+        self.pattern.duration()
+    }
+
+    fn spawn_between(
+        &mut self,
+        commands: &mut Commands, 
+        center: &Transform, 
+        sprite: Handle<Image>,
+        from: f32,
+        to: f32
+    ) {
+        self.pattern.set_dir( rotate_dir( self.dir, self.deg_per_second * from.max(0.) ) );
+        self.pattern.spawn_between( commands, center, sprite, from, to );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn volley() -> BoxedPattern {
+        Box::new( CirclePattern::default() )
+    }
+
+    #[test]
+    fn in_window_includes_from_and_excludes_to() {
+        // A single volley is due at 0
+        assert!(in_window(0., 0.1, 0.));
+        assert!(in_window(-0.1, 0.1, 0.));
+        assert!(!in_window(-0.1, 0., 0.));
+        assert!(!in_window(0.1, 0.2, 0.));
+
+        // Longer patterns stay due until their duration ends
+        assert!(in_window(0.9, 1.1, 1.));
+        assert!(in_window(1., 1.1, 1.));
+        assert!(!in_window(1.1, 1.2, 1.));
+    }
+
+    #[test]
+    fn combinator_durations() {
+        let sequence = SequencePattern::new()
+            .then(0.5, volley())
+            .then(1., Box::new( RepeatPattern::new(volley(), 3, 0.25) ));
+        assert_eq!(sequence.duration(), 2.);
+
+        let parallel = ParallelPattern { 
+            patterns: vec![volley(), Box::new( RepeatPattern::new(volley(), 5, 0.5) )] 
+        };
+        assert_eq!(parallel.duration(), 2.);
+
+        assert_eq!(RepeatPattern::new(volley(), 0, 1.).duration(), 0.);
+        assert_eq!(RepeatPattern::new(volley(), 1, 1.).duration(), 0.);
+
+        let rotating = RotatingPattern::new( Box::new( sequence ), 90. );
+        assert_eq!(rotating.duration(), 2.);
+    }
+
+    #[test]
+    fn rotate_dir_is_counter_clockwise() {
+        let dir = rotate_dir(Vec2::X, 90.);
+        assert!(dir.abs_diff_eq(Vec2::Y, 1e-6));
+    }
+}
@@ -1,6 +1,8 @@
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 
+use crate::projectile::ProjectileTargetingType;
+
 
 pub struct RapierPlugin;
 
@@ -9,4 +11,36 @@ impl Plugin for RapierPlugin {
         app.add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.) );
         app.add_plugins(RapierDebugRenderPlugin::default());
     }
-}
\ No newline at end of file
+}
+
+/// The collision group of every faction, so queries only return the pairs that matter
+pub const PLAYER_GROUP: Group = Group::GROUP_1;
+pub const PLAYER_PROJECTILE_GROUP: Group = Group::GROUP_2;
+pub const ENEMY_GROUP: Group = Group::GROUP_3;
+pub const ENEMY_PROJECTILE_GROUP: Group = Group::GROUP_4;
+pub const WALL_GROUP: Group = Group::GROUP_5;
+
+pub fn player_groups() -> CollisionGroups {
+    CollisionGroups::new( PLAYER_GROUP, ENEMY_GROUP | ENEMY_PROJECTILE_GROUP | WALL_GROUP )
+}
+
+pub fn enemy_groups() -> CollisionGroups {
+    CollisionGroups::new( ENEMY_GROUP, PLAYER_GROUP | PLAYER_PROJECTILE_GROUP | WALL_GROUP )
+}
+
+pub fn wall_groups() -> CollisionGroups {
+    CollisionGroups::new( WALL_GROUP, Group::ALL )
+}
+
+/// Projectiles that damage enemies are in the player's projectile group, and the other way around \
+/// ENVIRONMENT projectiles are in both groups, as they damage everyone
+pub fn projectile_groups(targeting: ProjectileTargetingType) -> CollisionGroups {
+    match targeting {
+        ProjectileTargetingType::PLAYER => 
+            CollisionGroups::new( PLAYER_PROJECTILE_GROUP, ENEMY_GROUP | WALL_GROUP ),
+        ProjectileTargetingType::ENEMY => 
+            CollisionGroups::new( ENEMY_PROJECTILE_GROUP, PLAYER_GROUP | WALL_GROUP ),
+        ProjectileTargetingType::ENVIRONMENT => 
+            CollisionGroups::new( PLAYER_PROJECTILE_GROUP | ENEMY_PROJECTILE_GROUP, PLAYER_GROUP | ENEMY_GROUP | WALL_GROUP ),
+    }
+}
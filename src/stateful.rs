@@ -1,16 +1,58 @@
+use std::marker::PhantomData;
+
 use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::states::AppSet;
 
-// pub struct StatefulPlugin;
+/// Ticks every Stateful<T>, and applies its states to T::Target \
+/// Add one for every State type, like `StatefulPlugin::<PState>::default()`
+pub struct StatefulPlugin<T: State> {
+    _state: PhantomData<T>,
+}
 
+impl<T: State> Default for StatefulPlugin<T> {
+    fn default() -> Self {
+        StatefulPlugin { _state: PhantomData }
+    }
+}
 
-// impl Plugin for StatefulPlugin {
-//     fn build(&self, app: &mut App) {
+/// The order Stateful systems run in FixedUpdate, 
+/// systems reacting to StatefulFinished should run before Finish
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+pub enum StatefulSet {
+    /// Advances the states, and sends the events
+    Tick,
+    /// Applies the entered and current states to their targets
+    Apply,
+    /// Runs the on_finish actions
+    Finish,
+}
 
-//     }
-// }
+impl<T: State> Plugin for StatefulPlugin<T> {
+    fn build(&self, app: &mut App) {
+        app.add_event::<StatefulEvent<T>>();
+        app.add_event::<StatefulFinished<T>>();
+        app.configure_sets(FixedUpdate, 
+            (
+                StatefulSet::Tick,
+                StatefulSet::Apply,
+                StatefulSet::Finish,
+            ).chain()
+        );
+        app.add_systems(FixedUpdate, 
+            (
+                increment_states::<T>.in_set(StatefulSet::Tick),
+                enter_states::<T>.in_set(StatefulSet::Apply),
+                apply_states::<T>.in_set(StatefulSet::Apply).after( enter_states::<T> ),
+                finish_states::<T>.in_set(StatefulSet::Finish),
+            ).in_set(AppSet::Gameplay)
+        );
+    }
+}
 
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Deserialize)]
 pub enum StateRepeat {
     /// State doesn't repeat.
     None,
@@ -19,15 +61,95 @@ pub enum StateRepeat {
     /// State repeats from the given index.
     FromIndex(usize)
 }
-#[derive(Clone, Copy, PartialEq)]
+
+impl StateRepeat {
+    /// Returns an error if it repeats from an index outside the states, 
+    /// or if the states it repeats are all Instant, which would never take any time
+    pub fn check<T: State>(&self, states: &[T]) -> Result<(), String> {
+        let repeated = match *self {
+            StateRepeat::None => return Ok(()),
+            StateRepeat::All => states,
+            StateRepeat::FromIndex( index ) => match states.get(index..) {
+                Some( repeated ) if !repeated.is_empty() => repeated,
+                _ => return Err( format!("state_repeat FromIndex({}) is out of bounds, there are only {} states", index, states.len()) ),
+            },
+        };
+        if repeated.iter().all( |state| state.get_duration() == StateDuration::Instant ) {
+            return Err( "state_repeat repeats only Instant states, they would never take any time".to_string() );
+        }
+        return Ok(());
+    }
+}
+#[derive(Clone, Copy, PartialEq, Deserialize)]
 pub enum StateDuration {
     /// Changes the state instantly, the given parameters are instantly applied.
     Instant,
     /// Doesn't guarantee that the whole parameter will apply in given time.
     Fixed(f32),
-    /// Stretches the given parameters to happen in the given time.
-    /// TODO: Should store initial max time as well to interpolate the parameters!!
-    Stretch(f32)
+    /// Stretches the given parameters to happen in the given time. \
+    /// The parameters are interpolated from their values at the start of the state, 
+    /// following the given easing curve.
+    Stretch(f32, Easing)
+}
+
+/// Curves used to interpolate the parameters of a StateDuration::Stretch state
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+pub enum Easing {
+    Linear,
+    /// Starts slow, speeds up towards the end
+    EaseIn,
+    /// Starts fast, slows down towards the end
+    EaseOut,
+    /// Slow at both ends, fast in the middle
+    EaseInOut,
+    /// Like EaseInOut, but softer
+    Sine,
+}
+
+impl Easing {
+    /// Maps a progress value between 0 and 1 onto the curve
+    pub fn ease(&self, t: f32) -> f32 {
+        let t = t.clamp(0., 1.);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => 1. - (1. - t) * (1. - t),
+            Easing::EaseInOut => {
+                if t < 0.5 { 2. * t * t } else { 1. - (-2. * t + 2.).powi(2) / 2. }
+            },
+            Easing::Sine => -((std::f32::consts::PI * t).cos() - 1.) / 2.,
+        }
+    }
+}
+
+/// Where the value of a state parameter comes from \
+/// Gets evaluated every tick with the time spent in the current state
+#[derive(Clone, Copy, Deserialize)]
+pub enum StateParam {
+    /// Always the same value
+    Constant(f32),
+    /// Starts from `from`, and changes by `rate` every second
+    Ramp { from: f32, rate: f32 },
+    /// `base + amplitude * sin(frequency * 2PI * t + phase)`, phase is in radians
+    Sine { base: f32, amplitude: f32, frequency: f32, phase: f32 },
+    /// Same as Sine, but with cos
+    Cosine { base: f32, amplitude: f32, frequency: f32, phase: f32 },
+}
+
+impl StateParam {
+    pub fn value(&self, time: f32) -> f32 {
+        use std::f32::consts::TAU;
+        match *self {
+            StateParam::Constant( value ) => value,
+            StateParam::Ramp { from, rate } => from + rate * time,
+            StateParam::Sine { base, amplitude, frequency, phase } => {
+                base + amplitude * (frequency * TAU * time + phase).sin()
+            },
+            StateParam::Cosine { base, amplitude, frequency, phase } => {
+                base + amplitude * (frequency * TAU * time + phase).cos()
+            },
+        }
+    }
 }
 
 
@@ -37,16 +159,77 @@ pub struct StatefulEvent<T: State> {
     pub state: T,
 }
 
-pub trait State: Copy {
+/// Sent once, when a sequence with StateRepeat::None runs out of states
+#[derive(Event)]
+pub struct StatefulFinished<T: State> {
+    pub entity_id: Entity,
+    _state: PhantomData<T>,
+}
+
+/// What happens with the entity when its sequence finishes
+#[derive(Clone, Deserialize)]
+#[serde(bound(deserialize = "T: Deserialize<'de>"))]
+pub enum StatefulFinish<T: State> {
+    /// Stays in the last state
+    None,
+    /// Despawns the entity, and its children
+    Despawn,
+    /// Starts over with another sequence, it doesn't finish again
+    Switch { states: Vec<T>, state_repeat: StateRepeat },
+}
+
+impl<T: State> StatefulFinish<T> {
+    /// Returns an error if the sequence it switches to can't be used
+    pub fn check(&self) -> Result<(), String> {
+        if let StatefulFinish::Switch { states, state_repeat } = self {
+            if states.is_empty() {
+                return Err( "on_finish Switch needs at least one state".to_string() );
+            }
+            state_repeat.check( states )?;
+        }
+        return Ok(());
+    }
+}
+
+/// A single state of a Stateful sequence, 
+/// it defines how it changes the Target component of the entity
+pub trait State: Copy + Send + Sync + 'static {
+    /// The component this state gets applied to
+    type Target: Component;
+
     fn get_duration(&self) -> StateDuration;
+
+    /// Called once, when the entity enters this state
+    fn enter(&self, _target: &mut Self::Target, _transform: &mut Transform) {}
+
+    /// Called every tick while the entity is in this state, not called for Instant states \
+    /// `time` is the time spent in the state, `progress` is the eased progress of Stretch states, 1 otherwise
+    fn apply(&self, _target: &mut Self::Target, _transform: &mut Transform, _time: f32, _progress: f32) {}
+
+    /// Used by StatefulFinish::Despawn, override it if the entities need to be despawned some other way
+    fn despawn(commands: &mut Commands, entity: Entity) {
+        commands.entity(entity).despawn_recursive();
+    }
 }
 
-#[derive(Component)]
+#[derive(Component, Clone)]
 pub struct Stateful<T: State>{
     pub state_current: usize,
+    /// The time left from the current state
     pub state_duration: StateDuration,
+    /// The whole length of the current state, needed to interpolate Stretch durations
+    pub state_time_max: f32,
+    /// The time spent in the current state, StateParam-s are evaluated with this
+    pub state_time: f32,
+    /// False until the first state gets sent out as an event
+    pub state_entered: bool,
+    /// Set when the sequence runs out of states, only happens with StateRepeat::None
+    pub finished: bool,
+    pub on_finish: StatefulFinish<T>,
     pub states: Vec<T>,
     pub state_repeat: StateRepeat,
+    /// Set after warning about a sequence stuck repeating Instant states, so it's only logged once
+    instant_warned: bool,
 }
 
 impl<T: State> Default for Stateful<T> {
@@ -54,8 +237,14 @@ impl<T: State> Default for Stateful<T> {
         Stateful {
             state_current: 0,
             state_duration: StateDuration::Fixed(1.),
+            state_time_max: 1.,
+            state_time: 0.,
+            state_entered: false,
+            finished: false,
+            on_finish: StatefulFinish::None,
             states: Vec::new(),
             state_repeat: StateRepeat::None,
+            instant_warned: false,
         }
     }
 
@@ -67,25 +256,63 @@ impl<T: State> Stateful<T> {
         Stateful {
             state_current: 0,
             state_duration: first_state.get_duration(),
+            state_time_max: duration_time( first_state.get_duration() ),
+            state_time: 0.,
+            state_entered: false,
+            finished: false,
+            on_finish: StatefulFinish::None,
             state_repeat,
             states,
+            instant_warned: false,
+        }
+    }
+
+    /// Sets what happens when the sequence finishes
+    pub fn with_on_finish(mut self, on_finish: StatefulFinish<T>) -> Stateful<T> {
+        self.on_finish = on_finish;
+        self
+    }
+
+    /// How far we are in the current state, eased, between 0 and 1 \
+    /// Only Stretch durations are interpolated, every other duration is instantly done
+    pub fn state_progress(&self) -> f32 {
+        match self.state_duration {
+            StateDuration::Stretch( time_left, easing ) if self.state_time_max > 0. => {
+                easing.ease( 1. - time_left / self.state_time_max )
+            },
+            _ => 1.
         }
     }
 }
 
-//Ideas: 
-// Have functions set the state, for example:
-//   -  Have the angular rotation be a sinus function
-//   -  The speed of a projectile gradually speed up
+/// Returns the time a duration takes, Instant takes none
+fn duration_time(duration: StateDuration) -> f32 {
+    match duration {
+        StateDuration::Instant => 0.,
+        StateDuration::Fixed( time ) | StateDuration::Stretch( time, _ ) => time,
+    }
+}
 
+/// Returns the same kind of duration with a different time
+fn with_time(duration: StateDuration, time: f32) -> StateDuration {
+    match duration {
+        StateDuration::Instant => StateDuration::Instant,
+        StateDuration::Fixed( _ ) => StateDuration::Fixed( time ),
+        StateDuration::Stretch( _, easing ) => StateDuration::Stretch( time, easing ),
+    }
+}
 
 impl<T: State> Stateful<T> {
     fn increment_state(&mut self) -> Option<StatefulEvent<T>> {
-        //TODO: This introduces a one frame delay in instant durations
         if self.state_current + 1 == self.states.len() {
             match self.state_repeat {
                 StateRepeat::All => self.state_current = 0,
-                StateRepeat::FromIndex(index) => self.state_current = index,
+                // The files are checked when they load, but a weapon can mix its repeat with the states of a pattern
+                StateRepeat::FromIndex(index) if index < self.states.len() => self.state_current = index,
+                StateRepeat::FromIndex(index) => {
+                    warn!("Stateful sequence can't repeat from index {}, it only has {} states", index, self.states.len());
+                    return None;
+                },
                 StateRepeat::None => return None
             }
         } else {
@@ -93,6 +320,8 @@ impl<T: State> Stateful<T> {
         }
         let state = self.states[ self.state_current ];
         self.state_duration = state.get_duration();                
+        self.state_time_max = duration_time( self.state_duration );
+        self.state_time = 0.;
 
         return Some( StatefulEvent{ 
             entity_id: Entity::PLACEHOLDER,
@@ -101,27 +330,352 @@ impl<T: State> Stateful<T> {
     }
 
 
-    pub fn update_state(&mut self,time_delta: f32) -> Option<StatefulEvent<T>> {
-        match self.state_duration {
-            StateDuration::Instant => {
-                return self.increment_state()
-            },
-            StateDuration::Fixed( time_left ) | StateDuration::Stretch( time_left ) => {
-
-                //TODO: Check if the extra frame delay is because of floating point errors, use EPSILON
-                if time_left <= 0. {
-                    return self.increment_state()
-                } else {
-                    // Decrement timer
-                    match self.state_duration {
-                        StateDuration::Fixed( time ) => self.state_duration = StateDuration::Fixed( time - time_delta),
-                        StateDuration::Stretch( time ) => self.state_duration = StateDuration::Stretch( time - time_delta),
-                        _ => () 
+    /// Advances the states by time_delta seconds \
+    /// The time left over after a state ends is carried into the next one, 
+    /// and any run of Instant states is resolved in the same tick. \
+    /// Returns every state that was entered, in order, including the first one.
+    pub fn update_state(&mut self, time_delta: f32) -> Vec<StatefulEvent<T>> {
+        let mut events = Vec::new();
+        if self.states.is_empty() {
+            return events;
+        }
+
+        if !self.state_entered {
+            self.state_entered = true;
+            events.push( StatefulEvent {
+                entity_id: Entity::PLACEHOLDER,
+                state: self.states[ self.state_current ],
+            });
+        }
+
+        let mut time_left = time_delta;
+        // Counts the states in a row that took no time, 
+        // so a sequence of only Instant states can't loop forever
+        let mut instant_run = 0;
+
+        loop {
+            let duration = duration_time( self.state_duration );
+
+            // The current state doesn't end this tick
+            if time_left < duration {
+                self.state_time += time_left;
+                self.state_duration = with_time( self.state_duration, duration - time_left );
+                break;
+            }
+
+            time_left -= duration;
+            self.state_time += duration;
+            self.state_duration = with_time( self.state_duration, 0. );
+
+            if duration <= 0. {
+                instant_run += 1;
+                if instant_run > self.states.len() {
+                    // The loaders reject these, but a weapon can mix its repeat with the states of a pattern
+                    if !self.instant_warned {
+                        warn!("Stateful sequence repeats only instant states, it won't advance past them");
+                        self.instant_warned = true;
                     }
+                    break;
+                }
+            } else {
+                instant_run = 0;
+            }
 
-                    return None
+            match self.increment_state() {
+                Some( event ) => events.push( event ),
+                None => {
+                    self.finished = true;
+                    break;
+                },
+            }
+        }
+
+        return events;
+    }
+
+    /// Runs the on_finish action, should be called once, when the sequence finishes
+    pub fn finish(&mut self, commands: &mut Commands, entity: Entity) {
+        match self.on_finish.clone() {
+            StatefulFinish::None => (),
+            StatefulFinish::Despawn => T::despawn(commands, entity),
+            StatefulFinish::Switch { states, state_repeat } => {
+                // Checked when the files load, an empty sequence would have nothing to start with
+                if !states.is_empty() {
+                    *self = Stateful::from_states( states, state_repeat );
                 }
+            },
+        }
+    }
+}
+
+
+fn increment_states<T: State> (
+    time: Res<Time>,
+    mut state_event: EventWriter<StatefulEvent<T>>,
+    mut finished_event: EventWriter<StatefulFinished<T>>,
+    mut entities: Query<(Entity, &mut Stateful<T>), With<T::Target>>,
+) { 
+    for (id, mut state) in entities.iter_mut() {
+        let was_finished = state.finished;
+
+        for mut event in state.update_state(time.delta_seconds()) {
+            event.entity_id = id;
+            state_event.send(event);
+        }
+
+        if state.finished && !was_finished {
+            finished_event.send( StatefulFinished::<T> {
+                entity_id: id,
+                _state: PhantomData,
+            });
+        }
+    }
+}
+
+/// Calls State::enter for every state that got entered this tick
+fn enter_states<T: State> (
+    mut state_event: EventReader<StatefulEvent<T>>,
+    mut entities: Query<(&mut T::Target, &mut Transform), With<Stateful<T>>>,
+) { 
+    for event in state_event.read() {
+        match entities.get_mut( event.entity_id ) {
+            Ok( (mut target, mut transform) ) => {
+                event.state.enter( &mut target, &mut transform );
+            },
+            Err(err) => warn!("enter_states lost entity from statefulevent, with error {}", err),
+        }
+    }
+}
+
+/// Calls State::apply with the current state of every entity
+fn apply_states<T: State> (
+    mut entities: Query<(&Stateful<T>, &mut T::Target, &mut Transform)>,
+) {
+    for (stateful, mut target, mut transform) in entities.iter_mut() {
+        // Instant states only get entered
+        if stateful.state_duration == StateDuration::Instant {
+            continue;
+        }
+        if let Some( state ) = stateful.states.get( stateful.state_current ) {
+            state.apply( &mut target, &mut transform, stateful.state_time, stateful.state_progress() );
+        }
+    }
+}
+
+/// Runs the on_finish action of the finished sequences, after everything else reacted to them
+fn finish_states<T: State> (
+    mut commands: Commands,
+    mut finished_event: EventReader<StatefulFinished<T>>,
+    mut entities: Query<&mut Stateful<T>>,
+) {
+    for event in finished_event.read() {
+        if let Ok( mut state ) = entities.get_mut( event.entity_id ) {
+            state.finish(&mut commands, event.entity_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::ecs::event::ManualEventReader;
+
+    use super::*;
+
+    #[derive(Component)]
+    struct Target;
+
+    #[derive(Clone, Copy)]
+    struct TestState(StateDuration);
+
+    impl State for TestState {
+        type Target = Target;
+
+        fn get_duration(&self) -> StateDuration {
+            self.0
+        }
+    }
+
+    fn fixed(durations: &[f32], state_repeat: StateRepeat) -> Stateful<TestState> {
+        let states = durations.iter().map( |time| TestState( StateDuration::Fixed(*time) ) ).collect();
+        return Stateful::from_states(states, state_repeat);
+    }
+
+    /// Runs the sequence for `ticks` ticks of `delta` seconds, 
+    /// and returns the index and the start time of every state entered
+    fn entered_states(delta: f32, ticks: u32) -> Vec<(usize, f32)> {
+        let mut stateful = fixed(&[0.5, 0.25, 0.3], StateRepeat::All);
+        let mut entered = Vec::new();
+        for tick in 1..=ticks {
+            // Every state is longer than a tick, so at most one gets entered per tick
+            if !stateful.update_state(delta).is_empty() {
+                entered.push( (stateful.state_current, tick as f32 * delta - stateful.state_time) );
             }
         }
+        return entered;
+    }
+
+    #[test]
+    fn enters_the_same_states_at_any_tick_rate() {
+        let slow = entered_states(1. / 30., 60);
+        let fast = entered_states(1. / 240., 480);
+
+        let expected = [(0, 0.), (1, 0.5), (2, 0.75), (0, 1.05), (1, 1.55), (2, 1.8)];
+        assert_eq!(slow.len(), expected.len());
+        assert_eq!(fast.len(), expected.len());
+        for ((slow, fast), expected) in slow.iter().zip(fast.iter()).zip(expected.iter()) {
+            assert_eq!(slow.0, expected.0);
+            assert_eq!(fast.0, expected.0);
+            assert!((slow.1 - expected.1).abs() < 1e-4, "{:?} != {:?}", slow, expected);
+            assert!((fast.1 - expected.1).abs() < 1e-4, "{:?} != {:?}", fast, expected);
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn carries_leftover_time_into_the_next_state() {
+        let mut stateful = fixed(&[0.1, 1.], StateRepeat::None);
+        let events = stateful.update_state(0.15);
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(stateful.state_current, 1);
+        assert!((stateful.state_time - 0.05).abs() < 1e-6);
+        match stateful.state_duration {
+            StateDuration::Fixed( left ) => assert!((left - 0.95).abs() < 1e-6),
+            _ => panic!("the duration changed kind"),
+        }
+    }
+
+    #[test]
+    fn instant_states_end_without_looping() {
+        let states = vec![TestState( StateDuration::Instant ); 3];
+
+        let mut once = Stateful::from_states(states.clone(), StateRepeat::None);
+        assert_eq!(once.update_state(0.1).len(), 3);
+        assert!(once.finished);
+        assert!(once.update_state(0.1).is_empty());
+
+        // Repeating only instant states would never end, it has to stop by itself
+        let mut repeating = Stateful::from_states(states, StateRepeat::All);
+        let events = repeating.update_state(0.1);
+        assert!(events.len() <= 2 * 3 + 1);
+        assert!(!repeating.finished);
+        assert!(repeating.instant_warned);
+    }
+
+    #[test]
+    fn check_rejects_repeating_only_instant_states() {
+        let instant = TestState( StateDuration::Instant );
+        let fixed = TestState( StateDuration::Fixed(1.) );
+
+        assert!(StateRepeat::None.check(&[instant, instant]).is_ok());
+        assert!(StateRepeat::All.check(&[instant, instant]).is_err());
+        assert!(StateRepeat::All.check(&[instant, fixed]).is_ok());
+        assert!(StateRepeat::FromIndex(1).check(&[fixed, instant]).is_err());
+        assert!(StateRepeat::FromIndex(2).check(&[fixed, instant]).is_err());
+
+        let switch = StatefulFinish::Switch { states: vec![instant], state_repeat: StateRepeat::All };
+        assert!(switch.check().is_err());
+        assert!(StatefulFinish::<TestState>::Switch { states: Vec::new(), state_repeat: StateRepeat::None }.check().is_err());
+    }
+
+    /// An App that ticks and finishes TestState sequences, with a manually advanced Time
+    fn stateful_app() -> App {
+        let mut app = App::new();
+        app.init_resource::<Time>();
+        app.add_event::<StatefulEvent<TestState>>();
+        app.add_event::<StatefulFinished<TestState>>();
+        app.add_systems(Update, (increment_states::<TestState>, finish_states::<TestState>).chain());
+        return app;
+    }
+
+    fn advance(app: &mut App, millis: u64) {
+        app.world.resource_mut::<Time>().advance_by( Duration::from_millis(millis) );
+        app.update();
+    }
+
+    #[test]
+    fn sends_finished_once() {
+        let mut app = stateful_app();
+        app.world.spawn( (fixed(&[0.1, 0.1], StateRepeat::None), Target) );
+
+        let mut reader = ManualEventReader::<StatefulFinished<TestState>>::default();
+        let mut finished = 0;
+        for _ in 0..20 {
+            advance(&mut app, 50);
+            finished += reader.read( app.world.resource::<Events<StatefulFinished<TestState>>>() ).count();
+        }
+        assert_eq!(finished, 1);
+    }
+
+    #[test]
+    fn despawns_when_finished() {
+        let mut app = stateful_app();
+        let id = app.world.spawn( (fixed(&[0.1], StateRepeat::None).with_on_finish( StatefulFinish::Despawn ), Target) ).id();
+
+        advance(&mut app, 50);
+        assert!(app.world.get_entity(id).is_some());
+        advance(&mut app, 100);
+        assert!(app.world.get_entity(id).is_none());
+    }
+
+    #[test]
+    fn switches_to_the_next_sequence_when_finished() {
+        let mut app = stateful_app();
+        let next = vec![TestState( StateDuration::Fixed(0.5) ), TestState( StateDuration::Fixed(0.25) )];
+        let stateful = fixed(&[0.1], StateRepeat::None)
+            .with_on_finish( StatefulFinish::Switch { states: next, state_repeat: StateRepeat::All } );
+        let id = app.world.spawn( (stateful, Target) ).id();
+
+        advance(&mut app, 150);
+        let stateful = app.world.get::<Stateful<TestState>>(id).unwrap();
+        assert!(!stateful.finished);
+        assert_eq!(stateful.states.len(), 2);
+        assert_eq!(stateful.state_current, 0);
+
+        // The new sequence starts from its first state, and repeats like it says
+        advance(&mut app, 600);
+        assert_eq!(app.world.get::<Stateful<TestState>>(id).unwrap().state_current, 1);
+        advance(&mut app, 200);
+        let stateful = app.world.get::<Stateful<TestState>>(id).unwrap();
+        assert_eq!(stateful.state_current, 0);
+        assert!(!stateful.finished);
+    }
+
+    #[test]
+    fn easing_curves_start_at_0_and_end_at_1() {
+        for easing in [Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut, Easing::Sine] {
+            assert!(easing.ease(0.).abs() < 1e-6);
+            assert!((easing.ease(1.) - 1.).abs() < 1e-6);
+            // Progress outside 0 and 1 is clamped
+            assert_eq!(easing.ease(-1.), easing.ease(0.));
+            assert_eq!(easing.ease(2.), easing.ease(1.));
+        }
+    }
+
+    #[test]
+    fn easing_curves_have_their_shape() {
+        assert_eq!(Easing::Linear.ease(0.25), 0.25);
+        assert!(Easing::EaseIn.ease(0.25) < 0.25);
+        assert!(Easing::EaseOut.ease(0.25) > 0.25);
+        assert!((Easing::EaseInOut.ease(0.5) - 0.5).abs() < 1e-6);
+        assert!((Easing::Sine.ease(0.5) - 0.5).abs() < 1e-6);
+        assert!(Easing::EaseInOut.ease(0.25) < 0.25);
+        assert!(Easing::EaseInOut.ease(0.75) > 0.75);
+    }
+
+    #[test]
+    fn state_params_follow_their_formula() {
+        use std::f32::consts::PI;
+
+        assert_eq!(StateParam::Constant(3.).value(10.), 3.);
+        assert_eq!(StateParam::Ramp { from: 2., rate: -4. }.value(0.5), 0.);
+
+        let sine = StateParam::Sine { base: 1., amplitude: 2., frequency: 0.5, phase: 0. };
+        assert!((sine.value(0.) - 1.).abs() < 1e-5);
+        assert!((sine.value(0.5) - 3.).abs() < 1e-5);
+
+        let cosine = StateParam::Cosine { base: 1., amplitude: 2., frequency: 1., phase: PI };
+        assert!((cosine.value(0.) + 1.).abs() < 1e-5);
+    }
+}
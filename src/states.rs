@@ -8,12 +8,25 @@ use crate::player::PlayerPlugin;
 use crate::input::{InputPlugin, Keybinds};
 use crate::entity::EntityPlugin;
 use crate::rapier::RapierPlugin;
+use crate::bulletpattern::BulletPatternPlugin;
+use crate::projectilepattern::PatternPlugin;
+use crate::emitter::EmitterPlugin;
+use crate::pool::ProjectilePoolPlugin;
+use crate::damage::DamagePlugin;
+use crate::weapon::WeaponPlugin;
+use crate::death::DeathPlugin;
+use crate::hit::HitPlugin;
+use crate::status::StatusPlugin;
+use crate::heal::HealPlugin;
+use crate::enemy::EnemyPlugin;
 // TODO: Plugin bundle for a gameplay, and an editor state
 
 #[derive(States, Debug, Hash, PartialEq, Eq, Clone)]
 pub enum AppStates {
     Editor,
-    Gameplay
+    Gameplay,
+    /// The player died, gameplay stops
+    GameOver,
 }
 
 impl AppStates {
@@ -21,6 +34,8 @@ impl AppStates {
         match self {
             AppStates::Editor => AppStates::Gameplay,
             AppStates::Gameplay => AppStates::Editor, 
+            // TODO: Restart the game from here
+            AppStates::GameOver => AppStates::GameOver,
         }
     }
 }
@@ -53,9 +68,20 @@ impl PluginGroup for GameplayPlugins {
     fn build(self) -> bevy::app::PluginGroupBuilder {
         PluginGroupBuilder::start::<Self>()
             .add(ProjectilePlugin)
+            .add(ProjectilePoolPlugin)
+            .add(DamagePlugin)
+            .add(WeaponPlugin)
+            .add(BulletPatternPlugin)
+            .add(PatternPlugin)
+            .add(EmitterPlugin)
             .add(RapierPlugin)
             .add(EntityPlugin)
             .add(HealthPlugin)
+            .add(DeathPlugin)
+            .add(HitPlugin)
+            .add(StatusPlugin)
+            .add(HealPlugin)
+            .add(EnemyPlugin)
             .add(PlayerPlugin)
     }
 }
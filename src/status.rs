@@ -0,0 +1,364 @@
+use bevy::{prelude::*, utils::{HashMap, HashSet}};
+use serde::Deserialize;
+
+use crate::{
+    damage::{DamageApplied, DamageEvent, DamageKind, DamageQueue, DamageStage},
+    death::Dead,
+    player::Player,
+    states::AppSet
+};
+
+pub struct StatusPlugin;
+
+impl Plugin for StatusPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ApplyStatus>();
+        app.add_systems(Startup, spawn_status_hud.in_set(AppSet::Gameplay));
+        app.add_systems(PostUpdate,
+            (
+                filter_invulnerable_status.in_set(DamageStage::Invulnerability),
+                apply_statuses.in_set(DamageStage::PostHit),
+            )
+        );
+        app.add_systems(Update,
+            (
+                update_statuses,
+                update_status_hud.after(update_statuses),
+            ).in_set(AppSet::Gameplay)
+        );
+    }
+}
+
+/// Slowed and speedy multiply the movement speed by these
+pub const SLOWED_MULTIPLIER: f32 = 0.5;
+pub const SPEEDY_MULTIPLIER: f32 = 1.5;
+/// Berserk multiplies the rate of fire by this
+pub const BERSERK_MULTIPLIER: f32 = 1.5;
+/// Damage dealt every second by every stack of bleeding
+pub const BLEED_DAMAGE: i32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum StatusKind {
+    /// Moves slower
+    Slowed,
+    /// Can't move
+    Paralyzed,
+    /// Defense doesn't reduce the damage taken
+    ArmorBroken,
+    /// Loses health every second, stacks
+    Bleeding,
+    /// Takes no damage
+    Invulnerable,
+    /// Moves faster
+    Speedy,
+    /// Fires faster
+    Berserk,
+}
+
+/// What happens when an effect is applied to an entity that already has it
+#[derive(Clone, Copy, PartialEq)]
+pub enum Stacking {
+    /// The duration is set to the longer of the two
+    Refresh,
+    /// The durations add up
+    Extend,
+    /// Adds stacks up to the limit, and refreshes the duration
+    Intensify(u32),
+}
+
+impl StatusKind {
+    pub fn stacking(&self) -> Stacking {
+        return match self {
+            StatusKind::Bleeding => Stacking::Intensify(5),
+            StatusKind::ArmorBroken => Stacking::Extend,
+            _ => Stacking::Refresh,
+        };
+    }
+
+    /// Short label and color of the HUD icon
+    pub fn icon(&self) -> (&'static str, Color) {
+        return match self {
+            StatusKind::Slowed => ("SLW", Color::rgb(0.3, 0.5, 1.)),
+            StatusKind::Paralyzed => ("PAR", Color::rgb(0.8, 0.8, 0.2)),
+            StatusKind::ArmorBroken => ("ARM", Color::rgb(0.6, 0.4, 0.2)),
+            StatusKind::Bleeding => ("BLD", Color::rgb(0.8, 0.1, 0.1)),
+            StatusKind::Invulnerable => ("INV", Color::rgb(0.9, 0.9, 0.9)),
+            StatusKind::Speedy => ("SPD", Color::rgb(0.2, 0.8, 0.3)),
+            StatusKind::Berserk => ("BRS", Color::rgb(0.9, 0.4, 0.1)),
+        };
+    }
+}
+
+/// A status effect a projectile applies on hit
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    /// Seconds it lasts
+    pub duration: f32,
+}
+
+/// An effect currently on an entity
+#[derive(Clone, Copy, Debug)]
+pub struct ActiveStatus {
+    pub kind: StatusKind,
+    pub remaining: f32,
+    pub stacks: u32,
+    /// Time until the next damage tick of effects like bleeding
+    pub tick: f32,
+}
+
+/// The status effects on an entity, gets added the first time it receives one
+#[derive(Component, Default)]
+pub struct StatusEffects {
+    pub effects: Vec<ActiveStatus>,
+}
+
+impl StatusEffects {
+    /// Adds the effect by the stacking rule of its kind
+    pub fn add(&mut self, effect: StatusEffect) {
+        let existing = self.effects.iter_mut().find( |e| e.kind == effect.kind );
+        match existing {
+            Some( active ) => match effect.kind.stacking() {
+                Stacking::Refresh => active.remaining = active.remaining.max( effect.duration ),
+                Stacking::Extend => active.remaining += effect.duration,
+                Stacking::Intensify( max_stacks ) => {
+                    active.stacks = (active.stacks + 1).min( max_stacks );
+                    active.remaining = active.remaining.max( effect.duration );
+                },
+            },
+            None => self.effects.push( ActiveStatus {
+                kind: effect.kind,
+                remaining: effect.duration,
+                stacks: 1,
+                tick: 1.,
+            }),
+        }
+    }
+
+    pub fn has(&self, kind: StatusKind) -> bool {
+        return self.effects.iter().any( |e| e.kind == kind );
+    }
+
+    /// Multiplies the movement speed of the entity
+    pub fn speed_multiplier(&self) -> f32 {
+        if self.has( StatusKind::Paralyzed ) {
+            return 0.;
+        }
+        let mut multiplier = 1.;
+        if self.has( StatusKind::Slowed ) {
+            multiplier *= SLOWED_MULTIPLIER;
+        }
+        if self.has( StatusKind::Speedy ) {
+            multiplier *= SPEEDY_MULTIPLIER;
+        }
+        return multiplier;
+    }
+
+    /// Multiplies the rate of fire of the entity
+    pub fn fire_rate_multiplier(&self) -> f32 {
+        if self.has( StatusKind::Berserk ) {
+            return BERSERK_MULTIPLIER;
+        }
+        return 1.;
+    }
+}
+
+/// Applies the effect to the target, if the damage from source got through to it this frame \
+/// Without a source it's applied no matter what, like from an ability
+#[derive(Event, Clone, Copy)]
+pub struct ApplyStatus {
+    pub source: Option<Entity>,
+    pub target: Entity,
+    pub effect: StatusEffect,
+}
+
+/// Marks the UI node that holds the status icons of the player
+#[derive(Component)]
+pub struct StatusHud;
+
+/// Removes the damage against entities with the Invulnerable status
+pub fn filter_invulnerable_status(
+    mut queue: ResMut<DamageQueue>,
+    targets: Query<&StatusEffects>,
+) {
+    queue.events.retain( |event| {
+        return !targets.get( event.target ).is_ok_and( |s| s.has( StatusKind::Invulnerable ) );
+    });
+}
+
+fn apply_statuses(
+    mut commands: Commands,
+    mut status_events: EventReader<ApplyStatus>,
+    mut applied_events: EventReader<DamageApplied>,
+    mut targets: Query<Option<&mut StatusEffects>, Without<Dead>>,
+) {
+    let hits: HashSet<(Option<Entity>, Entity)> = applied_events.read()
+        .map( |event| (event.source, event.target) )
+        .collect();
+
+    // Entities getting their first effects, they can get more than one in a frame
+    let mut added: HashMap<Entity, StatusEffects> = HashMap::new();
+
+    for event in status_events.read() {
+        if event.source.is_some() && !hits.contains( &(event.source, event.target) ) {
+            continue;
+        }
+        match targets.get_mut( event.target ) {
+            Ok( Some( mut statuses ) ) => statuses.add( event.effect ),
+            Ok( None ) => added.entry( event.target ).or_default().add( event.effect ),
+            Err(_) => (),
+        }
+    }
+
+    // The hit could have killed the target, then it's despawned before this gets applied
+    for (id, statuses) in added {
+        commands.entity(id).try_insert( statuses );
+    }
+}
+
+/// Counts down the effects, and deals the damage of bleeding
+fn update_statuses(
+    time: Res<Time>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut entities: Query<(Entity, &mut StatusEffects)>,
+) {
+    let delta = time.delta_seconds();
+    for (id, mut statuses) in entities.iter_mut() {
+        for effect in statuses.effects.iter_mut() {
+            effect.remaining -= delta;
+
+            if effect.kind == StatusKind::Bleeding {
+                effect.tick -= delta;
+                while effect.tick <= 0. {
+                    effect.tick += 1.;
+                    damage_events.send( DamageEvent {
+                        source: None,
+                        attacker: None,
+                        target: id,
+                        amount: BLEED_DAMAGE * effect.stacks as i32,
+                        kind: DamageKind::True,
+                        armor_piercing: true,
+                        periodic: true,
+                    });
+                }
+            }
+        }
+        statuses.effects.retain( |effect| effect.remaining > 0. );
+    }
+}
+
+fn spawn_status_hud(
+    mut commands: Commands,
+) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(8.),
+                left: Val::Px(8.),
+                column_gap: Val::Px(4.),
+                ..default()
+            },
+            ..default()
+        },
+        StatusHud,
+    ));
+}
+
+/// Shows an icon for every effect on the player, with its stacks
+fn update_status_hud(
+    mut commands: Commands,
+    hud: Query<Entity, With<StatusHud>>,
+    player: Query<Option<&StatusEffects>, With<Player>>,
+    mut shown: Local<Vec<(StatusKind, u32)>>,
+) {
+    let (hud, statuses) = match (hud.get_single(), player.get_single()) {
+        (Ok( hud ), Ok( statuses )) => (hud, statuses),
+        _ => return,
+    };
+
+    let current: Vec<(StatusKind, u32)> = statuses
+        .map( |s| s.effects.iter().map( |e| (e.kind, e.stacks) ).collect() )
+        .unwrap_or_default();
+    // Only rebuild the icons when the effects change
+    if *shown == current {
+        return;
+    }
+
+    commands.entity(hud).despawn_descendants();
+    commands.entity(hud).with_children( |parent| {
+        for (kind, stacks) in current.iter() {
+            let (label, color) = kind.icon();
+            let text = if *stacks > 1 { format!("{}x{}", label, stacks) } else { label.to_string() };
+            parent.spawn( NodeBundle {
+                style: Style {
+                    min_width: Val::Px(32.),
+                    height: Val::Px(20.),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: color.into(),
+                ..default()
+            }).with_children( |icon| {
+                icon.spawn( TextBundle::from_section( text, TextStyle { font_size: 12., color: Color::BLACK, ..default() } ) );
+            });
+        }
+    });
+    *shown = current;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn effect(kind: StatusKind, duration: f32) -> StatusEffect {
+        StatusEffect { kind, duration }
+    }
+
+    #[test]
+    fn refresh_keeps_the_longer_duration() {
+        let mut statuses = StatusEffects::default();
+        statuses.add( effect(StatusKind::Slowed, 3.) );
+        statuses.add( effect(StatusKind::Slowed, 1.) );
+        assert_eq!(statuses.effects.len(), 1);
+        assert_eq!(statuses.effects[0].remaining, 3.);
+
+        statuses.add( effect(StatusKind::Slowed, 5.) );
+        assert_eq!(statuses.effects[0].remaining, 5.);
+        assert_eq!(statuses.effects[0].stacks, 1);
+    }
+
+    #[test]
+    fn extend_adds_the_durations() {
+        let mut statuses = StatusEffects::default();
+        statuses.add( effect(StatusKind::ArmorBroken, 2.) );
+        statuses.add( effect(StatusKind::ArmorBroken, 1.5) );
+        assert_eq!(statuses.effects.len(), 1);
+        assert_eq!(statuses.effects[0].remaining, 3.5);
+    }
+
+    #[test]
+    fn intensify_stacks_up_to_the_limit() {
+        let mut statuses = StatusEffects::default();
+        for _ in 0..10 {
+            statuses.add( effect(StatusKind::Bleeding, 2.) );
+        }
+        statuses.add( effect(StatusKind::Bleeding, 1.) );
+        assert_eq!(statuses.effects.len(), 1);
+        assert_eq!(statuses.effects[0].stacks, 5);
+        assert_eq!(statuses.effects[0].remaining, 2.);
+    }
+
+    #[test]
+    fn different_kinds_dont_stack() {
+        let mut statuses = StatusEffects::default();
+        statuses.add( effect(StatusKind::Slowed, 1.) );
+        statuses.add( effect(StatusKind::Speedy, 1.) );
+        assert_eq!(statuses.effects.len(), 2);
+        assert_eq!(statuses.speed_multiplier(), SLOWED_MULTIPLIER * SPEEDY_MULTIPLIER);
+
+        statuses.add( effect(StatusKind::Paralyzed, 1.) );
+        assert_eq!(statuses.speed_multiplier(), 0.);
+    }
+}
@@ -0,0 +1,38 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::rapier::wall_groups;
+
+/// Marks static colliders projectiles can't pass through, what they do on impact is set by their WallHit
+#[derive(Component)]
+pub struct Wall;
+
+/// A solid, static wall, a rectangle of half_size around its transform
+#[derive(Bundle)]
+pub struct WallBundle {
+    pub sprite: SpriteBundle,
+    pub wall: Wall,
+    pub rigid_body: RigidBody,
+    pub collider: Collider,
+    pub groups: CollisionGroups,
+}
+
+impl WallBundle {
+    pub fn new(transform: Transform, half_size: Vec2) -> Self {
+        WallBundle {
+            sprite: SpriteBundle {
+                sprite: Sprite {
+                    color: Color::DARK_GRAY,
+                    custom_size: Some( half_size * 2. ),
+                    ..default()
+                },
+                transform,
+                ..default()
+            },
+            wall: Wall,
+            rigid_body: RigidBody::Fixed,
+            collider: Collider::cuboid(half_size.x, half_size.y),
+            groups: wall_groups(),
+        }
+    }
+}
@@ -1,37 +1,175 @@
-/// Generic weapon struct for the player to have \
-/// TODO: extract this more as a generic, and have implementations for different kinds of weapons?
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    utils::BoxedFuture
+};
+use serde::Deserialize;
+
+use crate::{
+    bulletpattern::BulletPattern,
+    damage::{DamageKind, DamageRange},
+    projectile::{PState, ProjectileTargetingType},
+    projectilepattern::{BoxedPattern, ProjectileTemplate},
+    stateful::StateRepeat
+};
+
+pub struct WeaponPlugin;
+
+impl Plugin for WeaponPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<WeaponDefinition>();
+        app.init_asset_loader::<WeaponDefinitionLoader>();
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum WeaponClass {
+    Staff,
+    Bow,
+    Sword,
+    Wand,
+}
+
+/// A weapon loaded from a `.weapon.ron` file in assets \
+/// The pattern file sets how the projectiles fly, the weapon overrides their damage, range, and sprite
+#[derive(Asset, TypePath, Deserialize)]
+pub struct WeaponDefinition {
+    pub name: String,
+    pub tier: u32,
+    pub class: WeaponClass,
+    /// Rolled for every projectile, before the attack of the player multiplies it
+    pub damage: DamageRange,
+    /// Replaces the damage kind of the pattern's projectiles
+    #[serde(default)]
+    pub damage_kind: Option<DamageKind>,
+    /// The projectiles ignore the defense of what they hit
+    #[serde(default)]
+    pub armor_piercing: bool,
+    /// Shots per second
+    pub rate_of_fire: f32,
+    /// Distance the projectiles can travel, None uses the range of the pattern
+    #[serde(default)]
+    pub range: Option<f32>,
+
+    /// Path of the projectile image, relative to assets, None uses the sprite of the pattern
+    #[serde(default)]
+    pub sprite: Option<String>,
+    #[serde(skip)]
+    pub sprite_handle: Option<Handle<Image>>,
+
+    /// Path of the `.pattern.ron` file the weapon fires, relative to assets
+    pub pattern: String,
+    #[serde(skip)]
+    pub pattern_handle: Handle<BulletPattern>,
+
+    /// Replaces the state sequence of the pattern's projectiles
+    #[serde(default)]
+    pub states: Option<Vec<PState>>,
+    #[serde(default)]
+    pub state_repeat: Option<StateRepeat>,
+}
+
+impl WeaponDefinition {
+    /// The name of the weapon, with its tier and class, like "Flak Staff (T2 Staff)"
+    pub fn label(&self) -> String {
+        return format!("{} (T{} {:?})", self.name, self.tier, self.class);
+    }
+
+    /// Seconds between shots
+    pub fn cooldown(&self) -> f32 {
+        return 1. / self.rate_of_fire.max(f32::EPSILON);
+    }
+
+    /// Changes a projectile of the pattern into one fired by this weapon, held by owner
+    pub fn apply_to(&self, template: &mut ProjectileTemplate, damage: DamageRange, owner: Entity) {
+        template.damage_range = Some(damage);
+        template.base.targeting_type = ProjectileTargetingType::PLAYER;
+        template.base.owner = Some(owner);
+        if let Some( kind ) = self.damage_kind {
+            template.base.damage_kind = kind;
+        }
+        template.base.armor_piercing |= self.armor_piercing;
+        if self.range.is_some() {
+            template.base.range = self.range;
+        }
+        if let Some( states ) = &self.states {
+            template.states = states.clone();
+        }
+        if let Some( state_repeat ) = self.state_repeat {
+            template.state_repeat = state_repeat;
+        }
+    }
+}
+
+/// The weapon a projectile was fired from
+#[derive(Component)]
+pub struct WeaponSource(pub Handle<WeaponDefinition>);
+
+#[derive(Default)]
+pub struct WeaponDefinitionLoader;
+
+impl AssetLoader for WeaponDefinitionLoader {
+    type Asset = WeaponDefinition;
+    type Settings = ();
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<WeaponDefinition, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+
+            let mut weapon: WeaponDefinition = ron::de::from_bytes(&bytes)?;
+            // Without its own states, the repeat is used with the states of the pattern, which isn't loaded yet
+            if let (Some( states ), Some( state_repeat )) = (&weapon.states, weapon.state_repeat) {
+                state_repeat.check( states )?;
+            }
+            weapon.pattern_handle = load_context.load(weapon.pattern.clone());
+            weapon.sprite_handle = weapon.sprite.clone().map( |sprite| load_context.load(sprite) );
+
+            Ok(weapon)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["weapon.ron"]
+    }
+}
+
+/// The weapon the player holds, the stats come from its WeaponDefinition
 pub struct Weapon {
-    pub damage: i32,
-    pub attack_speed: f32,
+    pub definition: Handle<WeaponDefinition>,
+    /// The pattern built from the files, kept between shots so patterns like spirals can keep rotating
+    pub active_pattern: Option<BoxedPattern>,
 
     attack_timer: f32,
 }
 
 impl Weapon {
+    pub fn new(definition: Handle<WeaponDefinition>) -> Self {
+        Weapon {
+            definition,
+            active_pattern: None,
+            attack_timer: 0.,
+        }
+    }
 
-    pub fn increment_attack_timer(&mut self, delta: f32) {
-        if self.attack_timer < self.attack_speed {
+    pub fn increment_attack_timer(&mut self, delta: f32, cooldown: f32) {
+        if self.attack_timer < cooldown {
             self.attack_timer += delta;
         }
     }
 
-    /// Checks if our attack_timer reached the specified attack speed, if yes, it resets it.
-    pub fn can_attack(&mut self) -> bool {
-        if self.attack_timer >= self.attack_speed {
-            self.attack_timer -= self.attack_speed;
+    /// Checks if our attack_timer reached the cooldown, if yes, it resets it.
+    pub fn can_attack(&mut self, cooldown: f32) -> bool {
+        if self.attack_timer >= cooldown {
+            self.attack_timer -= cooldown;
             return true;
         }
         return false;
-    } 
-}
-
-impl Default for Weapon {
-    fn default() -> Self {
-        Weapon {
-            damage: 1,
-            attack_speed: 0.1,
-
-            attack_timer: 0.
-        }
     }
-}
\ No newline at end of file
+}